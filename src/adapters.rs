@@ -0,0 +1,146 @@
+use std::ops::Sub;
+
+use crate::Prepeek;
+
+/// An iterator alternating between the elements of two iterators.
+///
+/// This `struct` is created by [`Prepeek::interleave`](crate::Prepeek::interleave).
+pub struct Interleave<A, B> {
+    a: A,
+    b: B,
+    flag: bool,
+}
+
+impl<A, B> Interleave<A, B> {
+    pub(crate) fn new(a: A, b: B) -> Self {
+        Self { a, b, flag: false }
+    }
+}
+
+impl<A, B> Iterator for Interleave<A, B>
+where
+    A: Iterator,
+    B: Iterator<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.flag = !self.flag;
+        if self.flag {
+            self.a.next().or_else(|| self.b.next())
+        } else {
+            self.b.next().or_else(|| self.a.next())
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (a_lo, a_hi) = self.a.size_hint();
+        let (b_lo, b_hi) = self.b.size_hint();
+        let hi = match (a_hi, b_hi) {
+            (Some(a_hi), Some(b_hi)) => a_hi.checked_add(b_hi),
+            _ => None,
+        };
+        (a_lo.saturating_add(b_lo), hi)
+    }
+}
+
+/// An iterator yielding each run of equal consecutive elements once.
+///
+/// This `struct` is created by [`Prepeek::dedup`](crate::Prepeek::dedup).
+pub struct Dedup<I: Iterator> {
+    iter: Prepeek<I, 1>,
+}
+
+impl<I: Iterator> Dedup<I> {
+    pub(crate) fn new(iter: I) -> Self {
+        Self {
+            iter: Prepeek::new(iter),
+        }
+    }
+}
+
+impl<I> Iterator for Dedup<I>
+where
+    I: Iterator,
+    I::Item: PartialEq,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let v = self.iter.next()?;
+        while self.iter.peek() == Some(&v) {
+            self.iter.next();
+        }
+        Some(v)
+    }
+}
+
+/// An iterator yielding the differences between consecutive elements, after the first one.
+///
+/// This `struct` is created by [`Prepeek::delta_encode`](crate::Prepeek::delta_encode).
+pub struct DeltaEncode<I: Iterator> {
+    iter: I,
+    prev: Option<I::Item>,
+}
+
+impl<I: Iterator> DeltaEncode<I> {
+    pub(crate) fn new(iter: I) -> Self {
+        Self { iter, prev: None }
+    }
+}
+
+impl<I> Iterator for DeltaEncode<I>
+where
+    I: Iterator,
+    I::Item: Sub<Output = I::Item> + Copy,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let v = self.iter.next()?;
+        let delta = match self.prev.replace(v) {
+            Some(prev) => v - prev,
+            None => v,
+        };
+        Some(delta)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// An iterator merging the elements of two sorted iterators into one sorted sequence.
+///
+/// This `struct` is created by [`Prepeek::merge`](crate::Prepeek::merge).
+pub struct Merge<A: Iterator, B: Iterator<Item = A::Item>> {
+    a: Prepeek<A, 1>,
+    b: Prepeek<B, 1>,
+}
+
+impl<A: Iterator, B: Iterator<Item = A::Item>> Merge<A, B> {
+    pub(crate) fn new(a: A, b: B) -> Self {
+        Self {
+            a: Prepeek::new(a),
+            b: Prepeek::new(b),
+        }
+    }
+}
+
+impl<A, B> Iterator for Merge<A, B>
+where
+    A: Iterator,
+    B: Iterator<Item = A::Item>,
+    A::Item: Ord,
+{
+    type Item = A::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.a.peek(), self.b.peek()) {
+            // on ties, elements of `a` come first
+            (Some(x), Some(y)) if y < x => self.b.next(),
+            (Some(_), _) => self.a.next(),
+            (None, _) => self.b.next(),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::Prepeek;
+
+/// A [`Prepeek`] with one of a few lookaheads, chosen at runtime.
+///
+/// This `enum` is created by [`AnyPrepeek::with_requested_depth`], and maps a runtime depth to
+/// a concrete `L`.
+pub enum AnyPrepeek<I: Iterator> {
+    L1(Prepeek<I, 1>),
+    L2(Prepeek<I, 2>),
+    L4(Prepeek<I, 4>),
+    L8(Prepeek<I, 8>),
+    L16(Prepeek<I, 16>),
+}
+
+macro_rules! dispatch {
+    ($value:expr, $p:pat => $e:expr) => {
+        match $value {
+            AnyPrepeek::L1($p) => $e,
+            AnyPrepeek::L2($p) => $e,
+            AnyPrepeek::L4($p) => $e,
+            AnyPrepeek::L8($p) => $e,
+            AnyPrepeek::L16($p) => $e,
+        }
+    };
+}
+
+impl<I: Iterator> AnyPrepeek<I> {
+    /// Creates a [`Prepeek`] with room for at least `requested` elements, but no more than `MAX`.
+    ///
+    /// The depth `requested.min(MAX)` is rounded up to the next available lookahead (1, 2, 4, 8 or
+    /// 16), and capped at 16. If rounding up would go past `MAX`, the largest available lookahead
+    /// below `MAX` is taken instead, so `MAX` is never exceeded.
+    ///
+    /// Using a `MAX` of 0 fails to compile, as there is no lookahead below it.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::AnyPrepeek;
+    ///
+    /// let iter = AnyPrepeek::with_requested_depth::<4>(0..100, 10);
+    ///
+    /// assert_eq!(iter.lookahead(), 4);
+    /// assert_eq!(iter.peek_nth_runtime(3), Some(&3));
+    /// ```
+    pub fn with_requested_depth<const MAX: usize>(iter: I, requested: usize) -> Self {
+        const { assert!(MAX >= 1, "with_requested_depth requires MAX >= 1") };
+        let rounded = match requested.clamp(1, MAX) {
+            1 => 1,
+            2 => 2,
+            3..=4 => 4,
+            5..=8 => 8,
+            _ => 16,
+        };
+        let ceiling = match MAX {
+            1 => 1,
+            2..=3 => 2,
+            4..=7 => 4,
+            8..=15 => 8,
+            _ => 16,
+        };
+        match rounded.min(ceiling) {
+            1 => AnyPrepeek::L1(Prepeek::new(iter)),
+            2 => AnyPrepeek::L2(Prepeek::new(iter)),
+            4 => AnyPrepeek::L4(Prepeek::new(iter)),
+            8 => AnyPrepeek::L8(Prepeek::new(iter)),
+            _ => AnyPrepeek::L16(Prepeek::new(iter)),
+        }
+    }
+
+    /// Returns the lookahead `L` of the wrapped [`Prepeek`].
+    pub fn lookahead(&self) -> usize {
+        match self {
+            AnyPrepeek::L1(_) => 1,
+            AnyPrepeek::L2(_) => 2,
+            AnyPrepeek::L4(_) => 4,
+            AnyPrepeek::L8(_) => 8,
+            AnyPrepeek::L16(_) => 16,
+        }
+    }
+
+    /// Returns a reference to the next() value without advancing the iterator.
+    pub fn peek(&self) -> Option<&I::Item> {
+        dispatch!(self, p => p.peek())
+    }
+
+    /// Returns a reference to the `nth` value without advancing the iterator.
+    ///
+    /// If `n` is greater or equal to [`AnyPrepeek::lookahead`], None is returned.
+    pub fn peek_nth_runtime(&self, n: usize) -> Option<&I::Item> {
+        dispatch!(self, p => p.get_requested(n))
+    }
+}
+
+impl<I: Iterator> Iterator for AnyPrepeek<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        dispatch!(self, p => p.next())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_requested_depth() {
+        let mut peekable = AnyPrepeek::with_requested_depth::<4>(0..10, 10);
+        assert!(matches!(peekable, AnyPrepeek::L4(_)));
+        assert_eq!(peekable.peek().cloned(), Some(0));
+        assert_eq!(peekable.peek_nth_runtime(3).cloned(), Some(3));
+        assert_eq!(peekable.peek_nth_runtime(4).cloned(), None);
+        assert_eq!(peekable.next(), Some(0));
+        assert_eq!(peekable.peek_nth_runtime(3).cloned(), Some(4));
+
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<16>(0..10, 0).lookahead(),
+            1
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<16>(0..10, 3).lookahead(),
+            4
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<64>(0..10, 40).lookahead(),
+            16
+        );
+    }
+
+    #[test]
+    fn test_with_requested_depth_ceiling() {
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<3>(0..10, 10).lookahead(),
+            2
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<3>(0..10, 2).lookahead(),
+            2
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<5>(0..10, 10).lookahead(),
+            4
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<5>(0..10, 3).lookahead(),
+            4
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<12>(0..10, 10).lookahead(),
+            8
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<12>(0..10, 6).lookahead(),
+            8
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<1>(0..10, 10).lookahead(),
+            1
+        );
+        assert_eq!(
+            AnyPrepeek::with_requested_depth::<20>(0..10, 20).lookahead(),
+            16
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use crate::DynPrepeek;
+
+/// A peekable iterator with both the iterator type and the lookahead erased.
+///
+/// This `struct` is created by [`Prepeek::boxed`](crate::Prepeek::boxed), and allows storing
+/// [`Prepeek`](crate::Prepeek) objects of different types together. The lookahead is kept, but is
+/// a runtime value.
+pub struct BoxedPrepeek<'a, T> {
+    inner: DynPrepeek<Box<dyn Iterator<Item = T> + 'a>>,
+}
+
+impl<'a, T> BoxedPrepeek<'a, T> {
+    pub(crate) fn new(inner: DynPrepeek<Box<dyn Iterator<Item = T> + 'a>>) -> Self {
+        Self { inner }
+    }
+
+    /// Returns a reference to the next() value without advancing the iterator.
+    pub fn peek(&self) -> Option<&T> {
+        self.inner.peek()
+    }
+
+    /// Returns a reference to the `nth` value without advancing the iterator.
+    ///
+    /// If `n` is greater or equal to [`BoxedPrepeek::lookahead`], None is returned.
+    pub fn peek_nth_runtime(&self, n: usize) -> Option<&T> {
+        self.inner.peek_nth(n)
+    }
+
+    /// Returns the lookahead, which is `L` of the original [`Prepeek`](crate::Prepeek).
+    pub fn lookahead(&self) -> usize {
+        self.inner.capacity()
+    }
+}
+
+impl<T> Iterator for BoxedPrepeek<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Prepeek;
+
+    #[test]
+    fn test_boxed() {
+        let mut peekable = Prepeek::<_, 2>::new(1..=4);
+        peekable.next();
+        let mut boxed = peekable.boxed();
+        assert_eq!(boxed.lookahead(), 2);
+        assert_eq!(boxed.peek().cloned(), Some(2));
+        assert_eq!(boxed.peek_nth_runtime(1).cloned(), Some(3));
+        assert_eq!(boxed.peek_nth_runtime(2).cloned(), None);
+        assert_eq!(boxed.next(), Some(2));
+        assert_eq!(boxed.peek_nth_runtime(1).cloned(), Some(4));
+
+        let mut all = vec![boxed];
+        all.push(Prepeek::<_, 3>::new([7, 8].into_iter()).boxed());
+        assert_eq!(all[1].peek().cloned(), Some(7));
+        assert_eq!(all[0].by_ref().collect::<Vec<_>>(), vec![3, 4]);
+    }
+}
@@ -0,0 +1,236 @@
+/// Wrapper struct to an iterator, like [`Prepeek`](crate::Prepeek) but with a lookahead chosen at
+/// runtime.
+///
+/// Internally holds the next elements in a heap-allocated ring, which can be grown with
+/// [`DynPrepeek::ensure_lookahead`].
+pub struct DynPrepeek<I: Iterator> {
+    iter: I,
+    ring: Vec<Option<I::Item>>,
+    ring_index: usize,
+    len: usize,
+}
+
+impl<I: Iterator> DynPrepeek<I> {
+    /// Creates a [`DynPrepeek`] object wrapping the given [`Iterator`], with room for
+    /// `lookahead` elements.
+    ///
+    /// Calls next() up to `lookahead` times on the iterator to fill up the internal buffer.
+    pub fn new(iter: I, lookahead: usize) -> Self {
+        let mut ring = Vec::with_capacity(lookahead);
+        ring.resize_with(lookahead, || None);
+        let mut s = Self {
+            iter,
+            ring,
+            ring_index: 0,
+            len: 0,
+        };
+        s.fill();
+        s
+    }
+
+    /// Creates a [`DynPrepeek`] with `buffered` as the first elements of the buffer, which is then
+    /// filled up from `iter`.
+    pub(crate) fn from_buffered(
+        iter: I,
+        buffered: impl Iterator<Item = I::Item>,
+        lookahead: usize,
+    ) -> Self {
+        let mut ring = Vec::with_capacity(lookahead);
+        ring.extend(buffered.take(lookahead).map(Some));
+        let len = ring.len();
+        ring.resize_with(lookahead, || None);
+        let mut s = Self {
+            iter,
+            ring,
+            ring_index: 0,
+            len,
+        };
+        s.fill();
+        s
+    }
+
+    /// Returns a reference to the next() value without advancing the iterator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::DynPrepeek;
+    ///
+    /// let mut iter = DynPrepeek::new([1, 2, 3].into_iter(), 2);
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.next(), Some(1));
+    /// ```
+    pub fn peek(&self) -> Option<&I::Item> {
+        self.peek_nth(0)
+    }
+
+    /// Returns a reference to the `nth` value without advancing the iterator.
+    ///
+    /// If `n` is greater or equal to [`DynPrepeek::capacity`], None is returned.
+    pub fn peek_nth(&self, n: usize) -> Option<&I::Item> {
+        if n >= self.len {
+            None
+        } else {
+            self.ring[(self.ring_index + n) % self.ring.len()].as_ref()
+        }
+    }
+
+    /// Returns an iterator over the buffered elements, in the order next() will yield them.
+    pub fn peeked(&self) -> impl Iterator<Item = &I::Item> + '_ {
+        (0..self.len).map_while(|n| self.peek_nth(n))
+    }
+
+    /// Returns the number of elements currently held in the buffer.
+    pub fn buffered_len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns how many elements the buffer can hold.
+    pub fn capacity(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Pulls from the inner iterator until at least `depth` elements are buffered, growing the
+    /// buffer if needed.
+    ///
+    /// Returns the number of buffered elements, which is lower than `depth` only if the inner
+    /// iterator ran out. The buffer keeps its new capacity afterwards.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::DynPrepeek;
+    ///
+    /// let mut iter = DynPrepeek::new(0..10, 1);
+    ///
+    /// assert_eq!(iter.peek_nth(4), None);
+    /// assert_eq!(iter.ensure_lookahead(5), 5);
+    /// assert_eq!(iter.peek_nth(4), Some(&4));
+    /// assert_eq!(iter.ensure_lookahead(20), 10);
+    /// ```
+    pub fn ensure_lookahead(&mut self, depth: usize) -> usize {
+        if depth > self.ring.len() {
+            self.ring.rotate_left(self.ring_index);
+            self.ring_index = 0;
+            self.ring.resize_with(depth, || None);
+        }
+        self.fill();
+        self.len
+    }
+
+    /// Shrinks the buffer to the elements it currently holds.
+    ///
+    /// This frees the room left over when the stream is shorter than the lookahead. The lookahead
+    /// becomes the number of buffered elements, and can be grown again with
+    /// [`DynPrepeek::ensure_lookahead`]. The order of the elements is unchanged.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::DynPrepeek;
+    ///
+    /// let mut iter = DynPrepeek::new([1, 2].into_iter(), 100);
+    /// iter.shrink_to_fit();
+    ///
+    /// assert_eq!(iter.capacity(), 2);
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2]);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.ring.rotate_left(self.ring_index);
+        self.ring_index = 0;
+        self.ring.truncate(self.len);
+        self.ring.shrink_to_fit();
+    }
+
+    /// Pulls from the inner iterator until the buffer is full, or the iterator returns `None`.
+    fn fill(&mut self) {
+        let capacity = self.ring.len();
+        while self.len < capacity {
+            match self.iter.next() {
+                Some(v) => {
+                    self.ring[(self.ring_index + self.len) % capacity] = Some(v);
+                    self.len += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for DynPrepeek<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ring.is_empty() {
+            return self.iter.next();
+        }
+        if self.len == 0 {
+            // the inner iterator may not be fused, try again
+            self.fill();
+            if self.len == 0 {
+                return None;
+            }
+        }
+        let v = self.ring[self.ring_index].take();
+        self.ring_index = (self.ring_index + 1) % self.ring.len();
+        self.len -= 1;
+        self.fill();
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple() {
+        let peekable = DynPrepeek::new([1, 2, 3].into_iter(), 2);
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth(1).cloned(), Some(2));
+        assert_eq!(peekable.peek_nth(2).cloned(), None);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let mut peekable = DynPrepeek::new([1, 2, 3].into_iter(), 0);
+        assert_eq!(peekable.peek().cloned(), None);
+        assert_eq!(peekable.next(), Some(1));
+    }
+
+    #[test]
+    fn test_ensure_lookahead() {
+        let mut peekable = DynPrepeek::new(0..100, 2);
+        assert_eq!(peekable.next(), Some(0));
+        assert_eq!(peekable.peek_nth(5).cloned(), None);
+
+        assert_eq!(peekable.ensure_lookahead(10), 10);
+        assert_eq!(peekable.capacity(), 10);
+        for n in 0..10 {
+            assert_eq!(peekable.peek_nth(n).cloned(), Some(n + 1));
+        }
+        assert_eq!(peekable.peek_nth(10).cloned(), None);
+
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek_nth(9).cloned(), Some(11));
+        assert!(peekable.eq(2..100));
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let mut peekable = DynPrepeek::new(1..=6, 16);
+        peekable.next();
+        peekable.next();
+        peekable.shrink_to_fit();
+        assert_eq!(peekable.capacity(), 4);
+        assert_eq!(peekable.peeked().collect::<Vec<_>>(), vec![&3, &4, &5, &6]);
+        assert_eq!(peekable.next(), Some(3));
+        assert_eq!(peekable.peeked().collect::<Vec<_>>(), vec![&4, &5, &6]);
+        assert!(peekable.eq(4..=6));
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// Errors returned by the fallible constructors of [`Prepeek`](crate::Prepeek).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrepeekError {
+    /// The lookahead `L` is 0, so nothing could ever be peeked.
+    ZeroLookahead,
+}
+
+impl fmt::Display for PrepeekError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrepeekError::ZeroLookahead => write!(
+                f,
+                "lookahead must be non-zero, use the inner iterator directly instead"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PrepeekError {}
+
+/// Error returned when the buffer of a [`Prepeek`](crate::Prepeek) would take more than the
+/// allowed number of bytes.
+///
+/// See [`Prepeek::try_new_checked`](crate::Prepeek::try_new_checked).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferTooLarge {
+    /// Size of the buffer in bytes.
+    pub size: usize,
+    /// Maximum size that was allowed, in bytes.
+    pub limit: usize,
+}
+
+impl fmt::Display for BufferTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "buffer of {} bytes exceeds the limit of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl std::error::Error for BufferTooLarge {}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+
+use crate::Prepeek;
+
+/// Wrapper to a [`Prepeek`] calling a closure on every value read by `peek` and `peek_nth`.
+///
+/// This `struct` is created by [`Prepeek::inspect_peek`].
+pub struct InspectPeek<I: Iterator, F, const L: usize> {
+    inner: Prepeek<I, L>,
+    f: RefCell<F>,
+}
+
+impl<I: Iterator, F: FnMut(&I::Item), const L: usize> InspectPeek<I, F, L> {
+    pub(crate) fn new(inner: Prepeek<I, L>, f: F) -> Self {
+        Self {
+            inner,
+            f: RefCell::new(f),
+        }
+    }
+
+    /// Like [`Prepeek::peek`], calling the closure if there is a value.
+    pub fn peek(&self) -> Option<&I::Item> {
+        self.peek_nth::<0>()
+    }
+
+    /// Like [`Prepeek::peek_nth`], calling the closure if there is a value.
+    pub fn peek_nth<const N: usize>(&self) -> Option<&I::Item> {
+        let v = self.inner.peek_nth::<N>();
+        if let Some(v) = v {
+            (self.f.borrow_mut())(v);
+        }
+        v
+    }
+}
+
+impl<I: Iterator, F, const L: usize> Iterator for InspectPeek<I, F, L> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inspect_peek() {
+        let mut peeked = Vec::new();
+        let mut peekable =
+            Prepeek::<_, 2>::new([1, 2, 3].into_iter()).inspect_peek(|x| peeked.push(*x));
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.next(), Some(2));
+        assert_eq!(peekable.peek().cloned(), Some(3));
+        drop(peekable);
+        assert_eq!(peeked, vec![1, 2, 3]);
+    }
+}
@@ -1,4 +1,61 @@
-use std::usize;
+mod adapters;
+mod any;
+mod boxed;
+mod dyn_prepeek;
+mod error;
+mod inspect;
+mod txn;
+mod underfill;
+
+pub use adapters::{Dedup, DeltaEncode, Interleave, Merge};
+pub use any::AnyPrepeek;
+pub use boxed::BoxedPrepeek;
+pub use dyn_prepeek::DynPrepeek;
+pub use error::{BufferTooLarge, PrepeekError};
+pub use inspect::InspectPeek;
+pub use txn::Txn;
+pub use underfill::OnUnderfill;
+
+use std::collections::VecDeque;
+use std::hash::Hash;
+use std::iter::{Cloned, Copied, Enumerate, FlatMap, Scan, TakeWhile};
+use std::ops::Sub;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Default buffer size limit of [`Prepeek::try_new_checked`], in bytes.
+pub const DEFAULT_MAX_BUFFER_SIZE: usize = 4096;
+
+/// What to do with the last buffered element when an element is pushed into a full buffer.
+///
+/// See [`Prepeek::set_overflow_policy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// The evicted element is dropped.
+    #[default]
+    DropEvicted,
+    /// The evicted element is returned to the caller.
+    ReturnEvicted,
+}
+
+/// An opaque marker of a point in a [`Prepeek`] which can be backtracked to.
+///
+/// This `struct` is created by [`Prepeek::checkpoint`], and is given back to either
+/// [`Prepeek::restore`] or [`Prepeek::discard`].
+#[derive(Debug)]
+#[must_use]
+pub struct Checkpoint(());
+
+/// The backtracking state of a [`Prepeek`], only allocated once a [`Checkpoint`] is taken.
+struct Backtrack<T> {
+    /// The elements consumed since the active checkpoint, if any.
+    log: Option<Vec<T>>,
+    /// Clones the consumed elements into the log.
+    clone: fn(&T) -> T,
+    /// The restored elements which did not fit back in the buffer, yielded before the inner
+    /// iterator.
+    spill: VecDeque<T>,
+}
 
 /// Wrapper struct to an iterator, offering `peek` and `peek_nth`.
 ///
@@ -7,25 +64,231 @@ pub struct Prepeek<I: Iterator, const L: usize> {
     iter: I,
     ring: [Option<I::Item>; L],
     ring_index: usize,
+    len: usize,
+    peek_limit: usize,
+    generation: u64,
+    overflow_policy: OverflowPolicy,
+    auto_refill: bool,
+    exhausted: bool,
+    backtrack: Option<Box<Backtrack<I::Item>>>,
+    #[cfg(feature = "metrics")]
+    max_peek_offset: AtomicUsize,
 }
 
 impl<I: Iterator, const L: usize> Prepeek<I, L> {
     /// Creates a [`Prepeek`] object wrapping the given [`Iterator`].
     ///
-    /// Calls next() `L` times on the iterator to fill up the internal buffer.
+    /// Calls next() up to `L` times on the iterator to fill up the internal buffer.
     pub fn new(iter: I) -> Self {
-        let mut s = Self {
-            iter,
-            ring: [const { None }; L],
-            ring_index: 0,
-        };
-        // fill ring buffer
-        for _ in 0..L {
-            s.next();
-        }
+        let mut s = Self::with_ring(iter, [const { None }; L], 0, 0);
+        s.fill();
         s
     }
 
+    /// Creates a [`Prepeek`] object from anything implementing [`IntoIterator`].
+    ///
+    /// This is a shorthand for calling [`Prepeek::new`] on `src.into_iter()`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::from_iter_with(vec![1, 2, 3]);
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// ```
+    pub fn from_iter_with<T: IntoIterator<IntoIter = I>>(src: T) -> Self {
+        Self::new(src.into_iter())
+    }
+
+    /// Creates a [`Prepeek`] object wrapping the given [`Iterator`], after dropping its first `skip`
+    /// elements.
+    ///
+    /// Unlike `Prepeek::new(iter.skip(skip))`, the inner iterator keeps its type `I`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new_skipping([1, 2, 3, 4].into_iter(), 2);
+    ///
+    /// assert_eq!(iter.peek(), Some(&3));
+    /// ```
+    pub fn new_skipping(mut iter: I, skip: usize) -> Self {
+        if skip > 0 {
+            iter.nth(skip - 1);
+        }
+        Self::new(iter)
+    }
+
+    /// Creates a [`Prepeek`] object from an inner iterator and the raw state of its buffer.
+    ///
+    /// The buffer is a ring: the next element is at `ring[ring_index]`, the one after at
+    /// `ring[(ring_index + 1) % L]`, and so on. Nothing is pulled from `iter`, so the returned
+    /// [`Prepeek`] yields the buffered elements, then those of `iter`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer breaks its invariants, that is if:
+    ///
+    /// - `ring_index` is not lower than `L`, or not 0 if `L` is 0.
+    /// - The buffered elements do not come first in the ring: a slot is `Some` after a `None` one
+    ///   when going through the ring from `ring_index`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let ring = [Some(3), Some(1), Some(2)];
+    /// let iter = Prepeek::from_raw_parts([4, 5].into_iter(), ring, 1);
+    ///
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    /// ```
+    pub fn from_raw_parts(iter: I, ring: [Option<I::Item>; L], ring_index: usize) -> Self {
+        assert!(
+            ring_index < L || (L == 0 && ring_index == 0),
+            "ring_index {ring_index} out of range for L = {L}"
+        );
+        let len = (0..L)
+            .take_while(|n| ring[(ring_index + n) % L].is_some())
+            .count();
+        assert!(
+            (len..L).all(|n| ring[(ring_index + n) % L].is_none()),
+            "buffered elements must come first in the ring"
+        );
+        Self::with_ring(iter, ring, ring_index, len)
+    }
+
+    /// Creates a [`Prepeek`] like [`Prepeek::new`], but fails if `L` is 0.
+    ///
+    /// A [`Prepeek`] with `L == 0` never has anything to peek at, which usually means `L` was
+    /// miscomputed. In that case, [`PrepeekError::ZeroLookahead`] is returned and the iterator is
+    /// dropped.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::{Prepeek, PrepeekError};
+    ///
+    /// let iter = Prepeek::<_, 2>::new_checked([1, 2, 3].into_iter()).unwrap();
+    /// assert_eq!(iter.peek(), Some(&1));
+    ///
+    /// let err = Prepeek::<_, 0>::new_checked([1, 2, 3].into_iter()).err();
+    /// assert_eq!(err, Some(PrepeekError::ZeroLookahead));
+    /// ```
+    pub fn new_checked(iter: I) -> Result<Self, PrepeekError> {
+        if L == 0 {
+            Err(PrepeekError::ZeroLookahead)
+        } else {
+            Ok(Self::new(iter))
+        }
+    }
+
+    /// Creates a [`Prepeek`] like [`Prepeek::new`], but fails if the buffer would take more than
+    /// [`DEFAULT_MAX_BUFFER_SIZE`] bytes.
+    ///
+    /// The buffer is an array of `L` elements stored inline, so a large `L` or a large `I::Item`
+    /// can overflow the stack. Note that this only enforces a size budget: the returned `Result`
+    /// holds the whole [`Prepeek`], so the caller reserves stack room for the buffer before the
+    /// check runs, and a buffer too large for the stack can still overflow it. Use
+    /// [`Prepeek::check_buffer_size`] to check before any [`Prepeek`] of that type is created,
+    /// and [`Prepeek::try_new_with_limit`] for a different limit.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// assert!(Prepeek::<_, 2>::try_new_checked([1u64, 2, 3].into_iter()).is_ok());
+    /// assert!(Prepeek::<_, 1024>::try_new_checked([1u64, 2, 3].into_iter()).is_err());
+    /// ```
+    pub fn try_new_checked(iter: I) -> Result<Self, BufferTooLarge> {
+        Self::try_new_with_limit(iter, DEFAULT_MAX_BUFFER_SIZE)
+    }
+
+    /// Creates a [`Prepeek`] like [`Prepeek::new`], but fails if the buffer would take more than
+    /// `limit` bytes.
+    ///
+    /// As with [`Prepeek::try_new_checked`], the stack room for the buffer is reserved by the
+    /// caller regardless of the outcome.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::{BufferTooLarge, Prepeek};
+    ///
+    /// let err = Prepeek::<_, 4>::try_new_with_limit([1u64, 2, 3].into_iter(), 32).err();
+    /// assert_eq!(err, Some(BufferTooLarge { size: 64, limit: 32 }));
+    /// ```
+    pub fn try_new_with_limit(iter: I, limit: usize) -> Result<Self, BufferTooLarge> {
+        Self::check_buffer_size(limit)?;
+        Ok(Self::new(iter))
+    }
+
+    /// Returns the size in bytes of the buffer of a [`Prepeek`] with this `I` and `L`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// assert_eq!(Prepeek::<std::vec::IntoIter<u64>, 4>::buffer_size(), 64);
+    /// ```
+    pub const fn buffer_size() -> usize {
+        std::mem::size_of::<[Option<I::Item>; L]>()
+    }
+
+    /// Checks that the buffer of a [`Prepeek`] with this `I` and `L` takes no more than `limit`
+    /// bytes, without creating one.
+    ///
+    /// Unlike [`Prepeek::try_new_with_limit`], this does not need any stack room for the buffer,
+    /// so it can guard against a stack overflow when run before calling into code which
+    /// instantiates the [`Prepeek`], for instance to fall back to a [`DynPrepeek`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::{BufferTooLarge, DynPrepeek, Prepeek, DEFAULT_MAX_BUFFER_SIZE};
+    ///
+    /// type Huge = Prepeek<std::ops::Range<u64>, 65536>;
+    ///
+    /// let err = Huge::check_buffer_size(DEFAULT_MAX_BUFFER_SIZE).err();
+    /// assert_eq!(
+    ///     err,
+    ///     Some(BufferTooLarge { size: Huge::buffer_size(), limit: DEFAULT_MAX_BUFFER_SIZE })
+    /// );
+    ///
+    /// let iter = DynPrepeek::new(0..10, 65536);
+    /// assert_eq!(iter.peek(), Some(&0));
+    /// ```
+    pub const fn check_buffer_size(limit: usize) -> Result<(), BufferTooLarge> {
+        let size = Self::buffer_size();
+        if size > limit {
+            Err(BufferTooLarge { size, limit })
+        } else {
+            Ok(())
+        }
+    }
+
     /// Returns a reference to the next() value without advancing the iterator.
     ///
     /// Like next, if there is a value, it is wrapped in a `Some(T)`. But if the iteration is over, `None` is returned.
@@ -75,73 +338,2688 @@ impl<I: Iterator, const L: usize> Prepeek<I, L> {
     /// assert_eq!(iter.peek_nth::<1>(), None);
     /// ```
     pub fn peek_nth<const N: usize>(&self) -> Option<&I::Item> {
-        // hopefully checked at compile-time at some point
-        if N >= L {
-            None
-        } else {
-            self.ring[(self.ring_index + N) % L].as_ref()
-        }
+        self.get_requested(N)
     }
-}
 
-impl<I: Iterator, const L: usize> Iterator for Prepeek<I, L> {
-    type Item = I::Item;
+    /// Returns a reference to the `n`th value, panicking if it is not buffered.
+    ///
+    /// This is meant for code where the element is known to be there, turning a broken assumption
+    /// into a clear failure.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no buffered element at offset `n`, with a message starting with `msg`
+    /// and followed by the buffered length and `L`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.peek_nth_expect(1, "two elements buffered"), &2);
+    /// ```
+    #[track_caller]
+    pub fn peek_nth_expect(&self, n: usize, msg: &str) -> &I::Item {
+        match self.get_requested(n) {
+            Some(v) => v,
+            None => panic!(
+                "{msg}: no element at offset {n} (buffered_len = {}, L = {L})",
+                self.len
+            ),
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut v = self.iter.next();
-        if L != 0 {
-            v = std::mem::replace(&mut self.ring[self.ring_index], v);
-            self.ring_index = (self.ring_index + 1) % L;
+    /// Returns a reference to the `n`th value, pulling from the inner iterator if it is not
+    /// buffered yet.
+    ///
+    /// Unlike [`Prepeek::peek_nth`], which only looks at what is already buffered, this takes
+    /// `&mut self` so that it can fill the buffer up to offset `n`. It therefore only returns
+    /// `None` if `n` is greater or equal to `L`, or if the inner iterator has no element left for
+    /// that offset. This matters for inner iterators which are not fused: once one returned `None`,
+    /// nothing more is buffered until next() is called, even if it has more elements.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut xs = [Some(1), None, Some(2)].into_iter();
+    /// let mut iter = Prepeek::<_, 2>::new(std::iter::from_fn(move || xs.next().flatten()));
+    ///
+    /// // The inner iterator returned `None` after `1`
+    /// assert_eq!(iter.peek_nth::<1>(), None);
+    /// assert_eq!(iter.peek_nth_fetch(1), Some(&2));
+    /// assert_eq!(iter.peek_nth::<1>(), Some(&2));
+    /// ```
+    pub fn peek_nth_fetch(&mut self, n: usize) -> Option<&I::Item> {
+        #[cfg(feature = "metrics")]
+        self.record_request(n);
+        if n >= L.min(self.peek_limit) {
+            return None;
         }
-        v
+        self.fill_to(n);
+        self.get(n)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Returns a mutable reference to the `nth` value, pulling from the inner iterator if it is not
+    /// buffered yet.
+    ///
+    /// Like [`Prepeek::peek_nth_fetch`], `None` is only returned if `n` is greater or equal to `L`,
+    /// or if the inner iterator has no element left for that offset.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 3>::new([1, 2, 3].into_iter());
+    ///
+    /// if let Some(x) = iter.peek_nth_mut_fetch(2) {
+    ///     *x = 30;
+    /// }
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 30]);
+    /// ```
+    pub fn peek_nth_mut_fetch(&mut self, n: usize) -> Option<&mut I::Item> {
+        #[cfg(feature = "metrics")]
+        self.record_request(n);
+        if n >= L.min(self.peek_limit) {
+            return None;
+        }
+        self.fill_to(n);
+        if n >= self.len {
+            return None;
+        }
+        self.generation = self.generation.wrapping_add(1);
+        self.ring[(self.ring_index + n) % L].as_mut()
+    }
 
-    #[test]
-    fn test_simple() {
-        let array = [1, 2, 3];
-        let mut peekable = Prepeek::<_, 2>::new(array.into_iter());
-        assert_eq!(peekable.peek().cloned(), Some(1));
-        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
-        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+    /// Limits how deep peeking can reach, so that offsets from `limit` on are seen as not buffered.
+    ///
+    /// This applies to all the methods peeking at the buffer, and keeps the `_fetch` methods from
+    /// pulling past `limit`. The buffer still holds up to `L` elements, so this only limits what
+    /// is visible. Methods which only use the buffer internally, such as
+    /// [`Prepeek::with_last_flag`], still see all of it. A `limit` of `L` or more removes the
+    /// limit.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    /// iter.set_peek_limit(1);
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.peek_nth::<1>(), None);
+    /// assert_eq!(iter.buffered_len(), 2);
+    /// ```
+    pub fn set_peek_limit(&mut self, limit: usize) {
+        let visible_len = self.visible_len();
+        self.peek_limit = limit;
+        if self.visible_len() != visible_len {
+            self.generation = self.generation.wrapping_add(1);
+        }
+    }
 
-        assert_eq!(peekable.next(), Some(1));
-        assert_eq!(peekable.peek().cloned(), Some(2));
-        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(3));
-        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+    /// Sets whether the inner iterator is called again after it returned `None`.
+    ///
+    /// Auto-refill is enabled by default: once the buffer is empty, every call to next() tries the
+    /// inner iterator again, which suits live sources that are only empty for a while. When
+    /// disabled, the first `None` returned by the inner iterator is final, and the [`Prepeek`]
+    /// behaves as if the inner iterator was fused. As this is decided at runtime, [`Prepeek`] does
+    /// not implement [`FusedIterator`](std::iter::FusedIterator); use [`Iterator::fuse`] on the
+    /// inner iterator to get one.
+    ///
+    /// Peeking takes `&self`, so it never calls the inner iterator, see
+    /// [`Prepeek::peek_nth_fetch`] for that.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut xs = [None, Some(1)].into_iter();
+    /// let mut iter = Prepeek::<_, 2>::new(std::iter::from_fn(move || xs.next().flatten()));
+    /// iter.set_auto_refill(false);
+    ///
+    /// assert_eq!(iter.next(), None);
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn set_auto_refill(&mut self, enabled: bool) {
+        self.auto_refill = enabled;
+    }
 
-        assert_eq!(peekable.next(), Some(2));
-        assert_eq!(peekable.peek().cloned(), Some(3));
-        assert_eq!(peekable.peek_nth::<1>().cloned(), None);
-        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+    /// Returns the number of elements currently held in the buffer.
+    ///
+    /// This is `L` until the inner iterator runs out, and then decreases with every call to next().
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.buffered_len(), 2);
+    /// iter.next();
+    /// iter.next();
+    /// assert_eq!(iter.buffered_len(), 1);
+    /// ```
+    pub fn buffered_len(&self) -> usize {
+        self.len
+    }
 
-        assert_eq!(peekable.next(), Some(3));
-        assert_eq!(peekable.peek().cloned(), None);
-        assert_eq!(peekable.peek_nth::<1>().cloned(), None);
-        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+    /// Returns a counter which changes whenever the buffer changes.
+    ///
+    /// This lets code that memoizes decisions on the buffer know if it needs to peek again, see
+    /// [`Prepeek::changed_since`]. Changes of what can be peeked at, such as through
+    /// [`Prepeek::set_peek_limit`], count as well.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// let generation = iter.generation();
+    /// assert!(!iter.changed_since(generation));
+    /// iter.next();
+    /// assert!(iter.changed_since(generation));
+    /// ```
+    pub fn generation(&self) -> u64 {
+        self.generation
     }
 
-    #[test]
-    fn test_no_prefetch() {
-        let array = [1, 2, 3];
-        let mut peekable = Prepeek::<_, 0>::new(array.into_iter());
-        assert_eq!(peekable.peek().cloned(), None);
-        assert_eq!(peekable.next(), Some(1));
+    /// Returns whether the buffer changed since [`Prepeek::generation`] returned `generation`.
+    pub fn changed_since(&self, generation: u64) -> bool {
+        self.generation != generation
     }
 
-    #[test]
-    fn test_overallocated() {
-        let array = [1, 2, 3];
-        let mut peekable = Prepeek::<_, 5>::new(array.into_iter());
+    /// Returns the deepest offset any caller requested so far, to help tuning `L`.
+    ///
+    /// The methods asking for an explicit offset count, that is [`Prepeek::peek`], the `peek_nth`
+    /// family and their `_mut` and `_fetch` variants, whether or not an element was there. Offsets
+    /// past the buffer are recorded as `L`, so reaching `L` means callers wanted more lookahead
+    /// than there is. Methods going through the buffer as a whole do not count, as they read as
+    /// far as it goes whatever `L` is. This is 0 if nothing was peeked at yet.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new([1, 2, 3, 4].into_iter());
+    /// iter.peek_nth::<2>();
+    /// iter.peek();
+    /// assert_eq!(iter.max_peek_offset(), 2);
+    ///
+    /// iter.peek_nth::<10>();
+    /// assert_eq!(iter.max_peek_offset(), 4);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn max_peek_offset(&self) -> usize {
+        self.max_peek_offset.load(Ordering::Relaxed)
+    }
+
+    /// Returns how full the buffer is, as `buffered_len() / L`.
+    ///
+    /// A ratio that stays well below `1.0` means `L` is larger than the streams it is used on. A
+    /// [`Prepeek`] with `L == 0` has nothing to fill and reports `1.0`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.fill_ratio(), 0.75);
+    /// ```
+    pub fn fill_ratio(&self) -> f64 {
+        if L == 0 {
+            1.0
+        } else {
+            self.buffered_len() as f64 / L as f64
+        }
+    }
+
+    /// Creates a wrapper calling `cb` in next() whenever [`Prepeek::fill_ratio`] drops below
+    /// `threshold` after yielding an element.
+    ///
+    /// This helps tuning `L` in long-running code. Taking the callback through a wrapper, rather
+    /// than storing it in `self`, lets it be any [`FnMut`], including closures borrowing their
+    /// environment, without costing anything to the [`Prepeek`]s which do not use it. The other
+    /// methods of the [`Prepeek`] stay reachable through [`OnUnderfill::get_ref`],
+    /// [`OnUnderfill::get_mut`] and [`OnUnderfill::into_inner`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    /// use std::cell::Cell;
+    ///
+    /// let count = Cell::new(0);
+    /// let mut iter = Prepeek::<_, 4>::new([1, 2, 3].into_iter())
+    ///     .on_underfill(0.5, || count.set(count.get() + 1));
+    ///
+    /// iter.next(); // 2 of 4 buffered
+    /// assert_eq!(count.get(), 0);
+    /// iter.next(); // 1 of 4 buffered
+    /// assert_eq!(count.get(), 1);
+    /// ```
+    pub fn on_underfill<F: FnMut()>(self, threshold: f64, cb: F) -> OnUnderfill<I, F, L> {
+        OnUnderfill::new(self, threshold, cb)
+    }
+
+    /// Returns references to the next `m` elements without advancing the iterator.
+    ///
+    /// `Some` is only returned if `m` elements are buffered, so `None` is returned if `m` is
+    /// greater than `L` or if the iterator ends before `m` elements. Since `m` is only known at
+    /// runtime, the references are collected into a [`Vec`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.peek_chunk(2), Some(vec![&1, &2]));
+    /// assert_eq!(iter.peek_chunk(4), None);
+    /// ```
+    pub fn peek_chunk(&self, m: usize) -> Option<Vec<&I::Item>> {
+        if m > L {
+            return None;
+        }
+        (0..m).map(|n| self.get(n)).collect()
+    }
+
+    /// Returns references to the next `N` elements as an array, without advancing the iterator.
+    ///
+    /// `Some` is only returned if `N` elements are buffered, so `None` is returned if `N` is
+    /// greater than `L` or if the iterator ends before `N` elements.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// if let Some([a, b]) = iter.peek_refs::<2>() {
+    ///     assert_eq!((a, b), (&1, &2));
+    /// }
+    /// assert_eq!(iter.peek_refs::<3>(), None);
+    /// ```
+    pub fn peek_refs<const N: usize>(&self) -> Option<[&I::Item; N]> {
+        if N > self.visible_len() {
+            return None;
+        }
+        Some(std::array::from_fn(|n| {
+            self.get(n).expect("offsets below len are buffered")
+        }))
+    }
+
+    /// Computes a key from the next two elements, which may be `None`.
+    ///
+    /// This makes looking up an LL(2) decision table straightforward.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new(['+', '='].into_iter());
+    ///
+    /// let op = iter.peek_pair_key(|a, b| match (a, b) {
+    ///     (Some('+'), Some('=')) => "add-assign",
+    ///     (Some('+'), _) => "add",
+    ///     _ => "other",
+    /// });
+    /// assert_eq!(op, "add-assign");
+    /// ```
+    pub fn peek_pair_key<K>(&self, f: impl Fn(Option<&I::Item>, Option<&I::Item>) -> K) -> K {
+        f(self.get(0), self.get(1))
+    }
+
+    /// Returns the keys of up to `depth` first buffered elements, to be used as a composite key.
+    ///
+    /// Fewer keys are returned if fewer elements are buffered, so that the end of the iteration
+    /// gives distinct keys.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    /// use std::collections::HashMap;
+    ///
+    /// let rules = HashMap::from([(vec!['a', 'b'], "ab")]);
+    /// let iter = Prepeek::<_, 2>::new("abc".chars());
+    ///
+    /// assert_eq!(rules.get(&iter.peek_key(2, |c| *c)), Some(&"ab"));
+    /// ```
+    pub fn peek_key<K: Hash + Eq>(&self, depth: usize, f: impl FnMut(&I::Item) -> K) -> Vec<K> {
+        self.buffered().take(depth).map(f).collect()
+    }
+
+    /// Returns references to the first and the last buffered elements.
+    ///
+    /// Both are the same reference if a single element is buffered, and both are `None` if the
+    /// buffer is empty.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter());
+    ///
+    /// assert_eq!(iter.peek_ends(), (Some(&1), Some(&3)));
+    /// ```
+    pub fn peek_ends(&self) -> (Option<&I::Item>, Option<&I::Item>) {
+        (
+            self.peek(),
+            self.visible_len().checked_sub(1).and_then(|n| self.get(n)),
+        )
+    }
+
+    /// Returns references to `count` buffered elements spread evenly across the buffer.
+    ///
+    /// The first and the last buffered elements are always included when `count` is at least 2.
+    /// If `count` is greater or equal to the number of buffered elements, all of them are
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 5>::new(1..10);
+    ///
+    /// assert_eq!(iter.peek_sample(3), vec![&1, &3, &5]);
+    /// ```
+    pub fn peek_sample(&self, count: usize) -> Vec<&I::Item> {
+        let len = self.visible_len();
+        match count {
+            0 => Vec::new(),
+            1 => self.peek().into_iter().collect(),
+            _ if count >= len => self.buffered().collect(),
+            // the step is at least 1, so offsets are distinct
+            _ => (0..count)
+                .filter_map(|i| self.get(i * (len - 1) / (count - 1)))
+                .collect(),
+        }
+    }
+
+    /// Returns an iterator over the buffered elements equal to the next one.
+    ///
+    /// This previews the next group of a [`slice::chunk_by`]-style grouping, as far as the buffer
+    /// reaches. The iterator stops at the first differing element or at the end of the buffer.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([1, 1, 2].into_iter());
+    ///
+    /// assert_eq!(iter.peek_group().collect::<Vec<_>>(), vec![&1, &1]);
+    /// ```
+    pub fn peek_group(&self) -> impl Iterator<Item = &I::Item> + '_
+    where
+        I::Item: PartialEq,
+    {
+        let front = self.peek();
+        self.buffered().take_while(move |x| Some(*x) == front)
+    }
+
+    /// Returns an iterator over the buffered elements sharing the key of the next one.
+    ///
+    /// Like [`Prepeek::peek_group`], but elements are compared by the result of `key`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([1, 3, 2].into_iter());
+    ///
+    /// assert_eq!(iter.peek_group_by(|x| x % 2).collect::<Vec<_>>(), vec![&1, &3]);
+    /// ```
+    pub fn peek_group_by<'a, K, F>(&'a self, mut key: F) -> impl Iterator<Item = &'a I::Item> + 'a
+    where
+        K: PartialEq + 'a,
+        F: FnMut(&I::Item) -> K + 'a,
+    {
+        let front = self.peek().map(&mut key);
+        self.buffered().take_while(move |x| Some(key(x)) == front)
+    }
+
+    /// Applies `f` to the buffered elements in order, collecting the results until `f` returns
+    /// `None`.
+    ///
+    /// Nothing is consumed, so this previews how much of the buffer `f` accepts.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new("12a".chars());
+    ///
+    /// assert_eq!(iter.peek_map_while(|c| c.to_digit(10)), vec![1, 2]);
+    /// ```
+    pub fn peek_map_while<T>(&self, f: impl FnMut(&I::Item) -> Option<T>) -> Vec<T> {
+        self.buffered().map_while(f).collect()
+    }
+
+    /// Applies `f` to the buffered elements in order, returning the offset and the result of the
+    /// first one for which `f` returns `Some`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new(["a", "1", "b"].into_iter());
+    ///
+    /// assert_eq!(iter.peek_find_map(|s| s.parse::<i32>().ok()), Some((1, 1)));
+    /// ```
+    pub fn peek_find_map<T>(&self, mut f: impl FnMut(&I::Item) -> Option<T>) -> Option<(usize, T)> {
+        self.buffered()
+            .enumerate()
+            .find_map(|(n, x)| f(x).map(|t| (n, t)))
+    }
+
+    /// Returns the offset `i` of the first pair of buffered elements at `i` and `i + 1` for which
+    /// `pred` returns `true`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new("ab1c".chars());
+    ///
+    /// assert_eq!(iter.find_pair(|a, b| a.is_ascii_digit() && b.is_alphabetic()), Some(2));
+    /// ```
+    pub fn find_pair(&self, mut pred: impl FnMut(&I::Item, &I::Item) -> bool) -> Option<usize> {
+        let mut buffered = self.buffered();
+        let mut prev = buffered.next()?;
+        buffered.position(|x| pred(std::mem::replace(&mut prev, x), x))
+    }
+
+    /// Returns the offsets of all buffered elements for which `pred` returns `true`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new("a,b,".chars());
+    ///
+    /// assert_eq!(iter.positions_in_buffer(|c| *c == ','), vec![1, 3]);
+    /// ```
+    pub fn positions_in_buffer(&self, mut pred: impl FnMut(&I::Item) -> bool) -> Vec<usize> {
+        self.buffered()
+            .enumerate()
+            .filter_map(|(n, x)| pred(x).then_some(n))
+            .collect()
+    }
+
+    /// Returns the offset of and a reference to the buffered element with the minimum key.
+    ///
+    /// If several elements are minimal, the first one is returned. `None` is returned if the
+    /// buffer is empty.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([3, 1, 2].into_iter());
+    ///
+    /// assert_eq!(iter.peek_min_by_key(|x| *x), Some((1, &1)));
+    /// ```
+    pub fn peek_min_by_key<K: Ord>(
+        &self,
+        mut key: impl FnMut(&I::Item) -> K,
+    ) -> Option<(usize, &I::Item)> {
+        self.buffered()
+            .enumerate()
+            .map(|(n, x)| (key(x), n, x))
+            .reduce(|best, cur| if cur.0 < best.0 { cur } else { best })
+            .map(|(_, n, x)| (n, x))
+    }
+
+    /// Returns the offset of and a reference to the buffered element with the maximum key.
+    ///
+    /// If several elements are maximal, the first one is returned. `None` is returned if the
+    /// buffer is empty.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([3, 1, 2].into_iter());
+    ///
+    /// assert_eq!(iter.peek_max_by_key(|x| *x), Some((0, &3)));
+    /// ```
+    pub fn peek_max_by_key<K: Ord>(
+        &self,
+        mut key: impl FnMut(&I::Item) -> K,
+    ) -> Option<(usize, &I::Item)> {
+        self.buffered()
+            .enumerate()
+            .map(|(n, x)| (key(x), n, x))
+            .reduce(|best, cur| if cur.0 > best.0 { cur } else { best })
+            .map(|(_, n, x)| (n, x))
+    }
+
+    /// Returns how many of the first buffered elements are strictly increasing.
+    ///
+    /// This is at least 1 if an element is buffered.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new([1, 2, 3, 2].into_iter());
+    ///
+    /// assert_eq!(iter.peek_ascending_run(), 3);
+    /// ```
+    pub fn peek_ascending_run(&self) -> usize
+    where
+        I::Item: PartialOrd,
+    {
+        self.run_len(|prev, x| prev < x)
+    }
+
+    /// Returns how many of the first buffered elements are strictly decreasing.
+    ///
+    /// This is at least 1 if an element is buffered.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new([3, 2, 2, 1].into_iter());
+    ///
+    /// assert_eq!(iter.peek_descending_run(), 2);
+    /// ```
+    pub fn peek_descending_run(&self) -> usize
+    where
+        I::Item: PartialOrd,
+    {
+        self.run_len(|prev, x| prev > x)
+    }
+
+    /// Sums clones of the buffered elements, without advancing the iterator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.peek_sum::<i32>(), 3);
+    /// ```
+    pub fn peek_sum<S: std::iter::Sum<I::Item>>(&self) -> S
+    where
+        I::Item: Clone,
+    {
+        self.buffered().cloned().sum()
+    }
+
+    /// Multiplies clones of the buffered elements, without advancing the iterator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new([2, 3, 4, 5].into_iter());
+    ///
+    /// assert_eq!(iter.peek_product::<i32>(), 24);
+    /// ```
+    pub fn peek_product<P: std::iter::Product<I::Item>>(&self) -> P
+    where
+        I::Item: Clone,
+    {
+        self.buffered().cloned().product()
+    }
+
+    /// Clones the buffered elements into a [`VecDeque`], in the order next() will yield them.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// let deque = iter.buffer_to_vecdeque();
+    /// assert_eq!(deque.front(), iter.peek());
+    /// assert_eq!(deque.back(), Some(&2));
+    /// ```
+    pub fn buffer_to_vecdeque(&self) -> VecDeque<I::Item>
+    where
+        I::Item: Clone,
+    {
+        self.buffered().cloned().collect()
+    }
+
+    /// Sets what happens to the last buffered element when pushing into a full buffer, as done by
+    /// [`Prepeek::put_back`].
+    ///
+    /// Defaults to [`OverflowPolicy::DropEvicted`].
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        self.overflow_policy = policy;
+    }
+
+    /// Puts `item` back at the front, so that it is the next element yielded.
+    ///
+    /// If the buffer is full, its last element is evicted to make room, and is lost for the
+    /// iteration. Depending on the [`OverflowPolicy`], the evicted element is then dropped or
+    /// returned. If `L` is 0, `item` itself is evicted.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::{OverflowPolicy, Prepeek};
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    /// iter.set_overflow_policy(OverflowPolicy::ReturnEvicted);
+    ///
+    /// assert_eq!(iter.next(), Some(1));
+    /// assert_eq!(iter.put_back(1), Some(3));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2]);
+    /// ```
+    pub fn put_back(&mut self, item: I::Item) -> Option<I::Item> {
+        let evicted = if L == 0 {
+            Some(item)
+        } else {
+            let evicted = if self.len == L { self.pop_back() } else { None };
+            self.push_front(item);
+            evicted
+        };
+        match self.overflow_policy {
+            OverflowPolicy::DropEvicted => None,
+            OverflowPolicy::ReturnEvicted => evicted,
+        }
+    }
+
+    /// Returns how many elements the buffer would hold once consecutive duplicates are collapsed.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new([1, 1, 2, 1].into_iter());
+    ///
+    /// assert_eq!(iter.buffer_dedup_len(), 3);
+    /// ```
+    pub fn buffer_dedup_len(&self) -> usize
+    where
+        I::Item: PartialEq,
+    {
+        let mut prev = None;
+        self.buffered()
+            .filter(|x| prev.replace(*x) != Some(*x))
+            .count()
+    }
+
+    /// Swaps the buffered elements at offsets `i` and `j`, changing the order they are yielded in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` or `j` is not lower than [`Prepeek::buffered_len`].
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    /// iter.swap(0, 1);
+    ///
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![2, 1, 3]);
+    /// ```
+    pub fn swap(&mut self, i: usize, j: usize) {
+        let len = self.buffered_len();
+        assert!(
+            i < len && j < len,
+            "swap offsets ({i}, {j}) out of range for buffered length {len}"
+        );
+        self.ring
+            .swap((self.ring_index + i) % L, (self.ring_index + j) % L);
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Removes the buffered elements for which `pred` returns `false`.
+    ///
+    /// The remaining elements keep their order and are moved to the front of the buffer. The
+    /// freed slots stay empty until the following call to next() fills the buffer up again from the
+    /// inner iterator, whose elements are not passed to `pred`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter());
+    /// iter.buffer_retain(|x| x % 2 == 1);
+    ///
+    /// assert_eq!(iter.peek_nth::<1>(), Some(&3));
+    /// assert_eq!(iter.peek_nth::<2>(), None);
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 3, 4]);
+    /// ```
+    pub fn buffer_retain(&mut self, mut pred: impl FnMut(&I::Item) -> bool) {
+        let mut kept = 0;
+        for n in 0..self.len {
+            let v = self.ring[(self.ring_index + n) % L].take();
+            if let Some(v) = v.filter(&mut pred) {
+                self.ring[(self.ring_index + kept) % L] = Some(v);
+                kept += 1;
+            }
+        }
+        if kept != self.len {
+            self.len = kept;
+            self.generation = self.generation.wrapping_add(1);
+        }
+    }
+
+    /// Creates an iterator moving out the buffered elements, without pulling anything from the
+    /// inner iterator.
+    ///
+    /// Once the returned iterator is exhausted, [`Prepeek::buffered_len`] is 0 while the inner
+    /// iterator is left untouched. The buffer is filled up again by the next call to next().
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.take_prefetched().collect::<Vec<_>>(), vec![1, 2]);
+    /// assert_eq!(iter.peek(), None);
+    /// assert_eq!(iter.next(), Some(3));
+    /// ```
+    pub fn take_prefetched(&mut self) -> impl Iterator<Item = I::Item> + '_ {
+        std::iter::from_fn(move || {
+            let v = self.pop_front()?;
+            self.log_consumed(&v);
+            Some(v)
+        })
+    }
+
+    /// Consumes a balanced group, from an opening element to its matching closing element.
+    ///
+    /// If the next element is an opener, elements are consumed while tracking nesting until the
+    /// matching closer, pulling from the inner iterator as needed. All consumed elements are
+    /// returned, including both ends. `None` is returned without consuming anything if the next
+    /// element is not an opener, and after consuming everything if the iteration ends before the
+    /// group is closed.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 1>::new("(a(b))c".chars());
+    ///
+    /// let group = iter.consume_balanced(|c| *c == '(', |c| *c == ')');
+    /// assert_eq!(group, Some("(a(b))".chars().collect()));
+    /// assert_eq!(iter.next(), Some('c'));
+    /// ```
+    pub fn consume_balanced(
+        &mut self,
+        is_open: impl Fn(&I::Item) -> bool,
+        is_close: impl Fn(&I::Item) -> bool,
+    ) -> Option<Vec<I::Item>> {
+        if !self.front().is_some_and(&is_open) {
+            return None;
+        }
+        let mut depth = 0usize;
+        let mut group = Vec::new();
+        for x in self.by_ref() {
+            if is_open(&x) {
+                depth += 1;
+            } else if is_close(&x) {
+                depth -= 1;
+            }
+            group.push(x);
+            if depth == 0 {
+                return Some(group);
+            }
+        }
+        None
+    }
+
+    /// Consumes `n` elements, telling whether all of them were available.
+    ///
+    /// Returns `Ok` with the `n` elements if the iteration did not end before, and `Err` with the
+    /// fewer elements consumed otherwise.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(iter.consume_exactly(2), Ok(vec![1, 2]));
+    /// assert_eq!(iter.consume_exactly(2), Err(vec![3]));
+    /// ```
+    pub fn consume_exactly(&mut self, n: usize) -> Result<Vec<I::Item>, Vec<I::Item>> {
+        let items: Vec<_> = self.by_ref().take(n).collect();
+        if items.len() == n {
+            Ok(items)
+        } else {
+            Err(items)
+        }
+    }
+
+    /// Begins a transaction, consuming elements speculatively.
+    ///
+    /// The elements consumed through the returned [`Txn`] are pushed back to the front if it is
+    /// rolled back or dropped, and removed for good if it is committed. As the buffer is not
+    /// refilled during the transaction, only the elements buffered at this point can be consumed.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    ///
+    /// let mut txn = iter.begin();
+    /// assert_eq!(txn.next(), Some(&1));
+    /// txn.rollback();
+    /// assert_eq!(iter.peek(), Some(&1));
+    ///
+    /// let mut txn = iter.begin();
+    /// assert_eq!(txn.next(), Some(&1));
+    /// assert_eq!(txn.commit(), vec![1]);
+    /// assert_eq!(iter.peek(), Some(&2));
+    /// ```
+    pub fn begin(&mut self) -> Txn<'_, I, L> {
+        Txn::new(self)
+    }
+
+    /// Marks the current position, so that the elements consumed from now on can be put back with
+    /// [`Prepeek::restore`].
+    ///
+    /// A clone of every element consumed, be it through next(), [`Prepeek::take_prefetched`] or a
+    /// committed [`Txn`], is kept in an undo log on the heap until the checkpoint is restored or
+    /// discarded. The log does not take room in the buffer, so the lookahead and the iteration
+    /// carry on as usual, but it grows with every element consumed in the meantime.
+    ///
+    /// Only one checkpoint can be active at a time. Taking a new checkpoint drops the undo log of
+    /// the previous one, and both markers then refer to the new one.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter());
+    ///
+    /// let cp = iter.checkpoint();
+    /// assert_eq!(iter.next(), Some(1));
+    /// assert_eq!(iter.next(), Some(2));
+    /// iter.restore(cp);
+    /// assert_eq!(iter.peek(), Some(&1));
+    ///
+    /// let cp = iter.checkpoint();
+    /// assert_eq!(iter.next(), Some(1));
+    /// iter.discard(cp);
+    /// assert_eq!(iter.peek(), Some(&2));
+    /// ```
+    pub fn checkpoint(&mut self) -> Checkpoint
+    where
+        I::Item: Clone,
+    {
+        let backtrack = self.backtrack.get_or_insert_with(|| {
+            Box::new(Backtrack {
+                log: None,
+                clone: I::Item::clone,
+                spill: VecDeque::new(),
+            })
+        });
+        backtrack.log = Some(Vec::new());
+        Checkpoint(())
+    }
+
+    /// Pushes the elements consumed since the checkpoint back to the front of the buffer.
+    ///
+    /// The elements come out of next() again in the order they were first yielded. If they do not
+    /// all fit in the buffer, the last buffered elements are moved out to make room, and are
+    /// yielded again before the rest of the inner iterator, so nothing is lost.
+    pub fn restore(&mut self, cp: Checkpoint) {
+        let Checkpoint(()) = cp;
+        let Some(mut backtrack) = self.backtrack.take() else {
+            return;
+        };
+        if let Some(log) = backtrack.log.take() {
+            for v in log.into_iter().rev() {
+                if self.len < L {
+                    self.push_front(v);
+                } else if let Some(back) = self.pop_back() {
+                    backtrack.spill.push_front(back);
+                    self.push_front(v);
+                } else {
+                    // `L` is 0, there is no buffer to restore to
+                    backtrack.spill.push_front(v);
+                }
+            }
+        }
+        if !backtrack.spill.is_empty() {
+            self.backtrack = Some(backtrack);
+        }
+    }
+
+    /// Drops the undo log of the checkpoint, keeping the elements consumed since then consumed.
+    pub fn discard(&mut self, cp: Checkpoint) {
+        let Checkpoint(()) = cp;
+        let Some(mut backtrack) = self.backtrack.take() else {
+            return;
+        };
+        backtrack.log = None;
+        if !backtrack.spill.is_empty() {
+            self.backtrack = Some(backtrack);
+        }
+    }
+
+    /// Creates a [`Prepeek`] merging the elements of `self` and `other`, which are both expected to
+    /// be sorted, into a sorted sequence.
+    ///
+    /// Each step yields the smaller of the two next elements, preferring `self` on ties. The buffer
+    /// of the returned [`Prepeek`] is filled up again over the merged sequence.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([2, 3].into_iter()).merge([1, 4].into_iter());
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    /// ```
+    pub fn merge<J: Iterator<Item = I::Item>>(
+        self,
+        other: J,
+    ) -> Prepeek<Merge<impl Iterator<Item = I::Item>, J>, L>
+    where
+        I::Item: Ord,
+    {
+        Prepeek::new(Merge::new(self.into_remaining(), other))
+    }
+
+    /// Creates a [`Prepeek`] alternating between the elements of `self` and `other`.
+    ///
+    /// Elements are taken from `self` first, then from `other`, and so on. Once one of the two
+    /// runs out, the remaining elements of the other are yielded in order. The buffer of the
+    /// returned [`Prepeek`] is filled up again over the interleaved sequence.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 3, 5].into_iter()).interleave([2, 4].into_iter());
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    /// ```
+    pub fn interleave<J: Iterator<Item = I::Item>>(
+        self,
+        other: J,
+    ) -> Prepeek<Interleave<impl Iterator<Item = I::Item>, J>, L> {
+        Prepeek::new(Interleave::new(self.into_remaining(), other))
+    }
+
+    /// Creates a wrapper calling `f` on each value read by its `peek` and `peek_nth` methods.
+    ///
+    /// The references returned by the peeks are left untouched, and consuming elements with
+    /// next() does not call `f` (see [`Iterator::inspect`] for that). Note that `f` runs on every
+    /// successful peek, so a loop peeking at the same position calls it every time.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    /// use std::cell::Cell;
+    ///
+    /// let peeks = Cell::new(0);
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter()).inspect_peek(|_| peeks.set(peeks.get() + 1));
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(peeks.get(), 2);
+    /// ```
+    pub fn inspect_peek<F: FnMut(&I::Item)>(self, f: F) -> InspectPeek<I, F, L> {
+        InspectPeek::new(self, f)
+    }
+
+    /// Creates a [`Prepeek`] over the elements of `self` mapped by `f` and flattened.
+    ///
+    /// Both the buffered and the remaining elements are mapped, and the buffer of the returned
+    /// [`Prepeek`] is filled up again over the flattened sequence.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2].into_iter()).flat_map(|x| [x; 2]);
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 1, 2, 2]);
+    /// ```
+    pub fn flat_map<J, F>(self, f: F) -> Prepeek<FlatMap<impl Iterator<Item = I::Item>, J, F>, L>
+    where
+        J: IntoIterator,
+        F: FnMut(I::Item) -> J,
+    {
+        Prepeek::new(self.into_remaining().flat_map(f))
+    }
+
+    /// Creates a [`Prepeek`] yielding the elements of `self` along with their index.
+    ///
+    /// Indices count from the next element of `self`, which gets index 0, so the buffered elements
+    /// are numbered before the remaining ones.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new(['a', 'b', 'c'].into_iter()).enumerate();
+    ///
+    /// assert_eq!(iter.peek_nth::<1>(), Some(&(1, 'b')));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![(0, 'a'), (1, 'b'), (2, 'c')]);
+    /// ```
+    pub fn enumerate(self) -> Prepeek<Enumerate<impl Iterator<Item = I::Item>>, L> {
+        Prepeek::new(self.into_remaining().enumerate())
+    }
+
+    /// Creates a [`Prepeek`] yielding the elements of `self` until `pred` returns `false`.
+    ///
+    /// `pred` is applied to the buffered elements before the remaining ones, and the buffer of the
+    /// returned [`Prepeek`] is filled up again over the bounded sequence.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3, 4].into_iter()).take_while(|x| *x < 3);
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2]);
+    /// ```
+    pub fn take_while<P: FnMut(&I::Item) -> bool>(
+        self,
+        pred: P,
+    ) -> Prepeek<TakeWhile<impl Iterator<Item = I::Item>, P>, L> {
+        Prepeek::new(self.into_remaining().take_while(pred))
+    }
+
+    /// Erases the iterator type and the lookahead of `self`, by boxing the inner iterator.
+    ///
+    /// The buffered elements are moved to the heap-allocated buffer of the returned
+    /// [`BoxedPrepeek`], which keeps a lookahead of `L`.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::{BoxedPrepeek, Prepeek};
+    ///
+    /// let iters: Vec<BoxedPrepeek<'_, i32>> = vec![
+    ///     Prepeek::<_, 1>::new([1, 2].into_iter()).boxed(),
+    ///     Prepeek::<_, 2>::new((3..5).map(|x| x * 10)).boxed(),
+    /// ];
+    ///
+    /// assert_eq!(iters[1].peek_nth_runtime(1), Some(&40));
+    /// ```
+    pub fn boxed<'a>(self) -> BoxedPrepeek<'a, I::Item>
+    where
+        I: 'a,
+    {
+        let (buffered, iter) = self.split();
+        let iter: Box<dyn Iterator<Item = I::Item> + 'a> = Box::new(iter);
+        BoxedPrepeek::new(DynPrepeek::from_buffered(iter, buffered, L))
+    }
+
+    /// Decomposes `self` into the inner iterator and the raw state of its buffer.
+    ///
+    /// This is the inverse of [`Prepeek::from_raw_parts`], see there for how the buffer is laid
+    /// out. Settings such as the [`OverflowPolicy`] are dropped, and so is the undo log of an
+    /// active [`Checkpoint`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if elements restored by [`Prepeek::restore`] did not fit in the buffer and are still
+    /// to be yielded, as they cannot be handed back with the inner iterator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+    /// iter.next();
+    ///
+    /// let (inner, ring, ring_index) = iter.into_parts();
+    /// assert_eq!(ring, [Some(3), Some(2)]);
+    /// assert_eq!(ring_index, 1);
+    /// assert_eq!(inner.count(), 0);
+    /// ```
+    pub fn into_parts(self) -> (I, [Option<I::Item>; L], usize) {
+        let Prepeek {
+            iter,
+            ring,
+            ring_index,
+            backtrack,
+            ..
+        } = self;
+        assert!(
+            backtrack.is_none_or(|b| b.spill.is_empty()),
+            "into_parts called with restored elements outside of the buffer"
+        );
+        (iter, ring, ring_index)
+    }
+
+    /// Creates a [`Prepeek`] over the results of `f`, which is given a mutable state along with
+    /// the elements of `self`, like [`Iterator::scan`].
+    ///
+    /// The buffered elements are passed through before the remaining ones, and the buffer of the
+    /// returned [`Prepeek`] is filled up again with the results.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].into_iter()).scan(0, |sum, x| {
+    ///     *sum += x;
+    ///     Some(*sum)
+    /// });
+    ///
+    /// assert_eq!(iter.peek_nth::<1>(), Some(&3));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 3, 6]);
+    /// ```
+    pub fn scan<St, B, F>(
+        self,
+        init: St,
+        f: F,
+    ) -> Prepeek<Scan<impl Iterator<Item = I::Item>, St, F>, L>
+    where
+        F: FnMut(&mut St, I::Item) -> Option<B>,
+    {
+        Prepeek::new(self.into_remaining().scan(init, f))
+    }
+
+    /// Creates a [`Prepeek`] yielding each run of equal consecutive elements of `self` once.
+    ///
+    /// The buffer of the returned [`Prepeek`] is filled up again over the deduplicated sequence.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 1, 2, 1].into_iter()).dedup();
+    ///
+    /// assert_eq!(iter.peek_nth::<1>(), Some(&2));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![1, 2, 1]);
+    /// ```
+    pub fn dedup(self) -> Prepeek<Dedup<impl Iterator<Item = I::Item>>, L>
+    where
+        I::Item: PartialEq,
+    {
+        Prepeek::new(Dedup::new(self.into_remaining()))
+    }
+
+    /// Creates a [`Prepeek`] yielding the first element of `self`, then the difference of each
+    /// element with the previous one.
+    ///
+    /// The buffer of the returned [`Prepeek`] is filled up again with the deltas.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([10, 13, 12].into_iter()).delta_encode();
+    ///
+    /// assert_eq!(iter.peek(), Some(&10));
+    /// assert_eq!(iter.collect::<Vec<_>>(), vec![10, 3, -1]);
+    /// ```
+    pub fn delta_encode(self) -> Prepeek<DeltaEncode<impl Iterator<Item = I::Item>>, L>
+    where
+        I::Item: Sub<Output = I::Item> + Copy,
+    {
+        Prepeek::new(DeltaEncode::new(self.into_remaining()))
+    }
+
+    /// Creates an iterator yielding each element along with a clone of the element following it.
+    ///
+    /// The follower is taken from the buffer, so `I::Item` has to be [`Clone`]. It is `None` for
+    /// the last element.
+    ///
+    /// Using an `L` of 0 fails to compile, as there is no buffer to take the follower from.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 1>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(
+    ///     iter.with_lookahead_pairs().collect::<Vec<_>>(),
+    ///     vec![(1, Some(2)), (2, Some(3)), (3, None)]
+    /// );
+    /// ```
+    ///
+    /// Without a buffer:
+    ///
+    /// ```compile_fail,E0080
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 0>::new([1, 2, 3].into_iter());
+    ///
+    /// iter.with_lookahead_pairs();
+    /// ```
+    pub fn with_lookahead_pairs(mut self) -> impl Iterator<Item = (I::Item, Option<I::Item>)>
+    where
+        I::Item: Clone,
+    {
+        const { assert!(L >= 1, "with_lookahead_pairs requires L >= 1") };
+        std::iter::from_fn(move || {
+            let item = self.next()?;
+            Some((item, self.front().cloned()))
+        })
+    }
+
+    /// Creates an iterator yielding each element along with whether it is the last one.
+    ///
+    /// The last element is detected with the buffer, so nothing more is pulled from the inner
+    /// iterator than with next().
+    ///
+    /// Using an `L` of 0 fails to compile, as there is no buffer to look ahead with.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 1>::new([1, 2, 3].into_iter());
+    ///
+    /// assert_eq!(
+    ///     iter.with_last_flag().collect::<Vec<_>>(),
+    ///     vec![(false, 1), (false, 2), (true, 3)]
+    /// );
+    /// ```
+    ///
+    /// Without a buffer:
+    ///
+    /// ```compile_fail,E0080
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 0>::new([1, 2, 3].into_iter());
+    ///
+    /// iter.with_last_flag();
+    /// ```
+    pub fn with_last_flag(mut self) -> impl Iterator<Item = (bool, I::Item)> {
+        const { assert!(L >= 1, "with_last_flag requires L >= 1") };
+        std::iter::from_fn(move || {
+            let item = self.next()?;
+            Some((self.front().is_none(), item))
+        })
+    }
+
+    /// Creates a [`Prepeek`] object with the given buffer and default settings.
+    fn with_ring(iter: I, ring: [Option<I::Item>; L], ring_index: usize, len: usize) -> Self {
+        Self {
+            iter,
+            ring,
+            ring_index,
+            len,
+            peek_limit: L,
+            generation: 0,
+            overflow_policy: OverflowPolicy::DropEvicted,
+            auto_refill: true,
+            exhausted: false,
+            backtrack: None,
+            #[cfg(feature = "metrics")]
+            max_peek_offset: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of buffered elements which can be peeked at.
+    fn visible_len(&self) -> usize {
+        self.len.min(self.peek_limit)
+    }
+
+    /// Returns the length of the run of buffered elements where each follows `cmp` with the
+    /// previous one.
+    fn run_len(&self, mut cmp: impl FnMut(&I::Item, &I::Item) -> bool) -> usize {
+        let mut buffered = self.buffered();
+        let Some(mut prev) = buffered.next() else {
+            return 0;
+        };
+        1 + buffered
+            .take_while(|x| cmp(std::mem::replace(&mut prev, x), x))
+            .count()
+    }
+
+    /// Returns a reference to the element at offset `n` of the buffer, if any.
+    fn get(&self, n: usize) -> Option<&I::Item> {
+        if n >= self.visible_len() {
+            None
+        } else {
+            self.ring[(self.ring_index + n) % L].as_ref()
+        }
+    }
+
+    /// Like [`Prepeek::get`], for an offset explicitly requested by a caller.
+    pub(crate) fn get_requested(&self, n: usize) -> Option<&I::Item> {
+        #[cfg(feature = "metrics")]
+        self.record_request(n);
+        self.get(n)
+    }
+
+    /// Raises the deepest requested offset to `n`, capped at `L`.
+    #[cfg(feature = "metrics")]
+    fn record_request(&self, n: usize) {
+        self.max_peek_offset.fetch_max(n.min(L), Ordering::Relaxed);
+    }
+
+    /// Records a clone of `v` in the undo log of the active [`Checkpoint`], if any.
+    pub(crate) fn log_consumed(&mut self, v: &I::Item) {
+        if let Some(backtrack) = &mut self.backtrack {
+            if let Some(log) = &mut backtrack.log {
+                log.push((backtrack.clone)(v));
+            }
+        }
+    }
+
+    /// Returns a reference to the front of the buffer, regardless of the peek limit.
+    fn front(&self) -> Option<&I::Item> {
+        if self.len == 0 {
+            None
+        } else {
+            self.ring[self.ring_index].as_ref()
+        }
+    }
+
+    /// Pulls from the inner iterator until offset `n` is buffered, or the iterator returns `None`.
+    ///
+    /// The buffered elements always come first in the ring, starting at `ring_index`, and the
+    /// `L - len` remaining slots are `None`.
+    fn fill_to(&mut self, n: usize) {
+        debug_assert!(n < L);
+        while self.len <= n {
+            match self.pull() {
+                Some(v) => {
+                    self.ring[(self.ring_index + self.len) % L] = Some(v);
+                    self.len += 1;
+                    self.generation = self.generation.wrapping_add(1);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Calls next() on the inner iterator, unless it already returned `None` and auto-refill is
+    /// disabled.
+    ///
+    /// Restored elements which did not fit in the buffer come first.
+    fn pull(&mut self) -> Option<I::Item> {
+        if let Some(backtrack) = &mut self.backtrack {
+            if let Some(v) = backtrack.spill.pop_front() {
+                if backtrack.spill.is_empty() && backtrack.log.is_none() {
+                    self.backtrack = None;
+                }
+                return Some(v);
+            }
+        }
+        if self.exhausted && !self.auto_refill {
+            return None;
+        }
+        let v = self.iter.next();
+        self.exhausted = v.is_none();
+        v
+    }
+
+    /// Fills up the whole buffer, as far as the inner iterator goes.
+    fn fill(&mut self) {
+        if L != 0 {
+            self.fill_to(L - 1);
+        }
+    }
+
+    /// Removes the front of the buffer, without refilling it.
+    fn pop_front(&mut self) -> Option<I::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        let v = self.ring[self.ring_index].take();
+        self.ring_index = (self.ring_index + 1) % L;
+        self.len -= 1;
+        self.generation = self.generation.wrapping_add(1);
+        v
+    }
+
+    /// Removes the back of the buffer.
+    fn pop_back(&mut self) -> Option<I::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        self.generation = self.generation.wrapping_add(1);
+        self.ring[(self.ring_index + self.len) % L].take()
+    }
+
+    /// Inserts `v` at the front of the buffer, which must not be full.
+    fn push_front(&mut self, v: I::Item) {
+        debug_assert!(self.len < L);
+        self.ring_index = (self.ring_index + L - 1) % L;
+        self.ring[self.ring_index] = Some(v);
+        self.len += 1;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Returns an iterator over the buffered elements, in the order next() will yield them.
+    fn buffered(&self) -> impl Iterator<Item = &I::Item> + '_ {
+        (0..L).map_while(|n| self.get(n))
+    }
+
+    /// Turns `self` into an iterator over the buffered elements followed by the rest of the
+    /// inner iterator, without prefetching anything.
+    fn into_remaining(self) -> impl Iterator<Item = I::Item> {
+        let (buffered, rest) = self.split();
+        buffered.chain(rest)
+    }
+
+    /// Splits `self` into an iterator over the buffered elements and one over the elements yet to
+    /// be pulled, which are the restored elements outside of the buffer followed by the inner
+    /// iterator.
+    fn split(self) -> (impl Iterator<Item = I::Item>, impl Iterator<Item = I::Item>) {
+        let Prepeek {
+            iter,
+            mut ring,
+            ring_index,
+            backtrack,
+            ..
+        } = self;
+        ring.rotate_left(ring_index);
+        let spill = backtrack.map(|b| b.spill).unwrap_or_default();
+        (ring.into_iter().flatten(), spill.into_iter().chain(iter))
+    }
+}
+
+impl<I: Iterator<Item = char>, const L: usize> Prepeek<I, L> {
+    /// Collects the buffered characters into a [`String`], without advancing the iterator.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 4>::new("fn main".chars());
+    ///
+    /// assert_eq!(iter.peek_str(), "fn m");
+    /// assert!(iter.peek_str().starts_with("fn"));
+    /// ```
+    pub fn peek_str(&self) -> String {
+        self.buffered().collect()
+    }
+
+    /// Returns whether the buffered characters start with `pattern`, without advancing the
+    /// iterator.
+    ///
+    /// This is `false` if `pattern` has more characters than are buffered, in particular if it
+    /// has more than `L` characters.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 3>::new("fn main".chars());
+    ///
+    /// assert!(iter.peek_chars_match("fn"));
+    /// assert!(!iter.peek_chars_match("fn main"));
+    /// ```
+    pub fn peek_chars_match(&self, pattern: &str) -> bool {
+        let mut buffered = self.buffered();
+        pattern.chars().all(|c| buffered.next() == Some(&c))
+    }
+}
+
+impl<T, I: Iterator<Item = Option<T>>, const L: usize> Prepeek<I, L> {
+    /// Returns a reference to the value inside the next() element without advancing the iterator.
+    ///
+    /// `None` is returned both if the next element is `None` and if the iteration is over. Use
+    /// [`Prepeek::peek`] to tell the two apart.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let mut iter = Prepeek::<_, 2>::new([Some(1), None].into_iter());
+    ///
+    /// assert_eq!(iter.peek_flatten(), Some(&1));
+    /// iter.next();
+    /// assert_eq!(iter.peek_flatten(), None);
+    /// assert_eq!(iter.peek(), Some(&None));
+    /// ```
+    pub fn peek_flatten(&self) -> Option<&T> {
+        self.peek()?.as_ref()
+    }
+}
+
+impl<'a, T: 'a, I: Iterator<Item = &'a T>, const L: usize> Prepeek<I, L> {
+    /// Creates a [`Prepeek`] over clones of the referenced elements.
+    ///
+    /// The buffer of the returned [`Prepeek`] is filled up again with the owned values.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let xs = [String::from("a"), String::from("b")];
+    /// let iter = Prepeek::<_, 2>::new(xs.iter()).cloned();
+    ///
+    /// assert_eq!(iter.peek(), Some(&String::from("a")));
+    /// ```
+    pub fn cloned(self) -> Prepeek<Cloned<impl Iterator<Item = &'a T>>, L>
+    where
+        T: Clone,
+    {
+        Prepeek::new(self.into_remaining().cloned())
+    }
+
+    /// Creates a [`Prepeek`] over copies of the referenced elements.
+    ///
+    /// The buffer of the returned [`Prepeek`] is filled up again with the owned values.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 2>::new([1, 2, 3].iter()).copied();
+    ///
+    /// assert_eq!(iter.peek(), Some(&1));
+    /// ```
+    pub fn copied(self) -> Prepeek<Copied<impl Iterator<Item = &'a T>>, L>
+    where
+        T: Copy,
+    {
+        Prepeek::new(self.into_remaining().copied())
+    }
+}
+
+impl<I: Iterator<Item = u8>, const L: usize> Prepeek<I, L> {
+    /// Computes the CRC-32 (IEEE) of the buffered bytes, without advancing the iterator.
+    ///
+    /// This allows validating, say, a frame header before consuming it.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use prepeek::Prepeek;
+    ///
+    /// let iter = Prepeek::<_, 9>::new(b"123456789".iter().copied());
+    ///
+    /// assert_eq!(iter.buffer_checksum(), 0xCBF43926);
+    /// ```
+    pub fn buffer_checksum(&self) -> u32 {
+        let crc = self.buffered().fold(!0u32, |mut crc, byte| {
+            crc ^= u32::from(*byte);
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB88320
+                } else {
+                    crc >> 1
+                };
+            }
+            crc
+        });
+        !crc
+    }
+}
+
+impl<I: Iterator, const L: usize> Iterator for Prepeek<I, L> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let v = if L == 0 {
+            self.pull()
+        } else {
+            if self.len == 0 {
+                // the inner iterator may not be fused, try again
+                self.fill();
+            }
+            let v = self.pop_front();
+            if v.is_some() {
+                self.fill();
+            }
+            v
+        };
+        if let Some(v) = &v {
+            self.log_consumed(v);
+        }
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple() {
+        let array = [1, 2, 3];
+        let mut peekable = Prepeek::<_, 2>::new(array.into_iter());
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek().cloned(), Some(2));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(3));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+
+        assert_eq!(peekable.next(), Some(2));
+        assert_eq!(peekable.peek().cloned(), Some(3));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), None);
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+
+        assert_eq!(peekable.next(), Some(3));
+        assert_eq!(peekable.peek().cloned(), None);
+        assert_eq!(peekable.peek_nth::<1>().cloned(), None);
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+    }
+
+    #[test]
+    fn test_no_prefetch() {
+        let array = [1, 2, 3];
+        let mut peekable = Prepeek::<_, 0>::new(array.into_iter());
+        assert_eq!(peekable.peek().cloned(), None);
+        assert_eq!(peekable.next(), Some(1));
+    }
+
+    #[test]
+    fn test_overallocated() {
+        let array = [1, 2, 3];
+        let mut peekable = Prepeek::<_, 5>::new(array.into_iter());
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.next(), Some(2));
+        assert_eq!(peekable.next(), Some(3));
+        assert_eq!(peekable.peek_nth::<0>().cloned(), None);
+        assert_eq!(peekable.peek_nth::<1>().cloned(), None);
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+    }
+
+    #[test]
+    fn test_new_checked() {
+        let array = [1, 2, 3];
+        let peekable = Prepeek::<_, 1>::new_checked(array.into_iter()).unwrap();
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert!(matches!(
+            Prepeek::<_, 0>::new_checked(array.into_iter()),
+            Err(PrepeekError::ZeroLookahead)
+        ));
+    }
+
+    #[test]
+    fn test_interleave() {
+        let mut peekable =
+            Prepeek::<_, 2>::new([1, 3, 5].into_iter()).interleave([2, 4].into_iter());
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_peek_chunk() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter());
+        assert_eq!(peekable.peek_chunk(0), Some(vec![]));
+        assert_eq!(peekable.peek_chunk(3), Some(vec![&1, &2, &3]));
+        assert_eq!(peekable.peek_chunk(4), None);
+
+        peekable.next();
+        peekable.next();
+        assert_eq!(peekable.peek_chunk(2), Some(vec![&3, &4]));
+        assert_eq!(peekable.peek_chunk(3), None);
+    }
+
+    #[test]
+    fn test_peek_group() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 1, 2, 2].into_iter());
+        assert_eq!(peekable.peek_group().collect::<Vec<_>>(), vec![&1, &1]);
+        assert_eq!(peekable.peek_group_by(|_| ()).count(), 3);
+
+        peekable.next();
+        peekable.next();
+        assert_eq!(peekable.peek_group().collect::<Vec<_>>(), vec![&2, &2]);
+
+        peekable.next();
+        peekable.next();
+        assert_eq!(peekable.peek_group().next(), None);
+    }
+
+    #[test]
+    fn test_with_lookahead_pairs() {
+        let peekable = Prepeek::<_, 2>::new(["a", "b"].into_iter());
+        let mut pairs = peekable.with_lookahead_pairs();
+        assert_eq!(pairs.next(), Some(("a", Some("b"))));
+        assert_eq!(pairs.next(), Some(("b", None)));
+        assert_eq!(pairs.next(), None);
+    }
+
+    #[test]
+    fn test_from_iter_with() {
+        let mut peekable = Prepeek::<_, 2>::from_iter_with(vec![1, 2, 3]);
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.next(), Some(1));
+    }
+
+    #[test]
+    fn test_swap() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 3].into_iter());
+        peekable.swap(0, 2);
+        assert_eq!(peekable.peek().cloned(), Some(3));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_swap_out_of_range() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2].into_iter());
+        peekable.swap(0, 2);
+    }
+
+    #[test]
+    fn test_peek_map_while() {
+        let peekable = Prepeek::<_, 4>::new([1, 2, -1, 3].into_iter());
+        assert_eq!(
+            peekable.peek_map_while(|x| (*x >= 0).then_some(*x)),
+            vec![1, 2]
+        );
+        assert_eq!(peekable.peek().cloned(), Some(1));
+    }
+
+    #[test]
+    fn test_peek_nth_fetch() {
+        let mut xs = [Some(1), None, Some(2), Some(3)].into_iter();
+        let mut peekable = Prepeek::<_, 3>::new(std::iter::from_fn(move || xs.next().flatten()));
+        assert_eq!(peekable.buffered_len(), 1);
+        assert_eq!(peekable.peek_nth::<1>().cloned(), None);
+        assert_eq!(peekable.peek_nth_fetch(1).cloned(), Some(2));
+        assert_eq!(peekable.peek_nth_fetch(2).cloned(), Some(3));
+        assert_eq!(peekable.peek_nth_fetch(3).cloned(), None);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_flat_map() {
+        let mut peekable =
+            Prepeek::<_, 3>::new([1, 2, 3].into_iter()).flat_map(|n| std::iter::repeat_n(n, n));
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(2));
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![2, 2, 3, 3, 3]);
+    }
+
+    #[test]
+    fn test_try_new_checked() {
+        let peekable = Prepeek::<_, 4>::try_new_checked([1u64, 2, 3].into_iter()).unwrap();
+        assert_eq!(peekable.peek().cloned(), Some(1));
+
+        let err = Prepeek::<_, 1024>::try_new_checked([1u64, 2, 3].into_iter()).err();
+        assert_eq!(
+            err,
+            Some(BufferTooLarge {
+                size: 1024 * std::mem::size_of::<Option<u64>>(),
+                limit: DEFAULT_MAX_BUFFER_SIZE,
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_buffer_size() {
+        type Huge = Prepeek<std::ops::Range<u64>, 65536>;
+        // no room for the buffer is needed to check it
+        let check = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| Huge::check_buffer_size(DEFAULT_MAX_BUFFER_SIZE))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(
+            check,
+            Err(BufferTooLarge {
+                size: 65536 * std::mem::size_of::<Option<u64>>(),
+                limit: DEFAULT_MAX_BUFFER_SIZE,
+            })
+        );
+        assert_eq!(
+            Prepeek::<std::ops::Range<u64>, 4>::check_buffer_size(DEFAULT_MAX_BUFFER_SIZE),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_peek_nth_mut_fetch() {
+        let mut xs = [Some(1), None, Some(2), Some(3)].into_iter();
+        let mut peekable = Prepeek::<_, 3>::new(std::iter::from_fn(move || xs.next().flatten()));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+        *peekable.peek_nth_mut_fetch(2).unwrap() += 10;
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(13));
+        assert_eq!(peekable.peek_nth_mut_fetch(3), None);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 13]);
+    }
+
+    #[test]
+    fn test_peek_sum_product() {
+        let peekable = Prepeek::<_, 4>::new([1, 2, 3].into_iter());
+        assert_eq!(peekable.peek_sum::<i32>(), 6);
+        assert_eq!(peekable.peek_product::<i32>(), 6);
+        assert_eq!(peekable.peek().cloned(), Some(1));
+
+        let peekable = Prepeek::<_, 4>::new(std::iter::empty::<i32>());
+        assert_eq!(peekable.peek_sum::<i32>(), 0);
+        assert_eq!(peekable.peek_product::<i32>(), 1);
+    }
+
+    #[test]
+    fn test_take_prefetched() {
+        let mut peekable = Prepeek::<_, 3>::new(1..=5);
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(
+            peekable.take_prefetched().collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+        assert_eq!(peekable.buffered_len(), 0);
+        assert_eq!(peekable.peek().cloned(), None);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![5]);
+    }
+
+    #[test]
+    fn test_peek_refs() {
+        let mut peekable = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+        assert_eq!(peekable.peek_refs::<2>(), Some([&1, &2]));
+        assert_eq!(peekable.peek_refs::<0>(), Some([]));
+        peekable.next();
+        peekable.next();
+        assert_eq!(peekable.peek_refs::<1>(), Some([&3]));
+        assert_eq!(peekable.peek_refs::<2>(), None);
+    }
+
+    #[test]
+    fn test_generation() {
+        let mut peekable = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+        let generation = peekable.generation();
+        peekable.peek();
+        peekable.peek_nth::<1>();
+        assert!(!peekable.changed_since(generation));
+
+        peekable.next();
+        assert!(peekable.changed_since(generation));
+        let generation = peekable.generation();
+        peekable.swap(0, 1);
+        assert!(peekable.changed_since(generation));
+
+        // draining the buffer still changes it, exhausted calls do not
+        peekable.next();
+        peekable.next();
+        let generation = peekable.generation();
+        assert_eq!(peekable.next(), None);
+        assert!(!peekable.changed_since(generation));
+
+        // changing what can be peeked at changes the buffer as seen by callers
+        let mut peekable = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+        let generation = peekable.generation();
+        peekable.set_peek_limit(2);
+        assert!(!peekable.changed_since(generation));
+        peekable.set_peek_limit(1);
+        assert!(peekable.changed_since(generation));
+        let generation = peekable.generation();
+        peekable.set_peek_limit(2);
+        assert!(peekable.changed_since(generation));
+    }
+
+    #[test]
+    fn test_buffer_retain() {
+        let mut peekable = Prepeek::<_, 4>::new(1..=6);
+        peekable.buffer_retain(|x| x % 2 == 1);
+        assert_eq!(peekable.buffered_len(), 2);
+        assert_eq!(peekable.peek_nth::<0>().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(3));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.buffered_len(), 3);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![3, 5, 6]);
+    }
+
+    #[test]
+    fn test_peek_ends() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 3].into_iter());
+        assert_eq!(peekable.peek_ends(), (Some(&1), Some(&3)));
+        peekable.next();
+        peekable.next();
+        let (first, last) = peekable.peek_ends();
+        assert!(std::ptr::eq(first.unwrap(), last.unwrap()));
+        peekable.next();
+        assert_eq!(peekable.peek_ends(), (None, None));
+    }
+
+    #[test]
+    fn test_enumerate() {
+        let mut peekable = Prepeek::<_, 2>::new(10..20);
+        peekable.next();
+        let mut peekable = peekable.enumerate();
+        assert_eq!(peekable.peek().cloned(), Some((0, 11)));
+        assert_eq!(peekable.next(), Some((0, 11)));
+        assert_eq!(peekable.next(), Some((1, 12)));
+        assert_eq!(peekable.next(), Some((2, 13)));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some((4, 15)));
+    }
+
+    #[test]
+    fn test_put_back() {
+        let mut peekable = Prepeek::<_, 2>::new(1..=4);
+        assert_eq!(peekable.put_back(0), None);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![0, 1, 3, 4]);
+
+        let mut peekable = Prepeek::<_, 2>::new(1..=4);
+        peekable.set_overflow_policy(OverflowPolicy::ReturnEvicted);
+        assert_eq!(peekable.put_back(0), Some(2));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![0, 1, 3, 4]);
+
+        let mut peekable = Prepeek::<_, 3>::new(1..=2);
+        peekable.set_overflow_policy(OverflowPolicy::ReturnEvicted);
+        assert_eq!(peekable.put_back(0), None);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        let mut peekable = Prepeek::<_, 0>::new(1..=2);
+        peekable.set_overflow_policy(OverflowPolicy::ReturnEvicted);
+        assert_eq!(peekable.put_back(0), Some(0));
+    }
+
+    #[test]
+    fn test_buffer_dedup_len() {
+        let peekable = Prepeek::<_, 6>::new([1, 1, 2, 2, 2, 3].into_iter());
+        assert_eq!(peekable.buffer_dedup_len(), 3);
+        let peekable = Prepeek::<_, 2>::new(std::iter::empty::<i32>());
+        assert_eq!(peekable.buffer_dedup_len(), 0);
+    }
+
+    #[test]
+    fn test_take_while() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter()).take_while(|x| *x < 3);
+        assert_eq!(peekable.buffered_len(), 2);
         assert_eq!(peekable.next(), Some(1));
         assert_eq!(peekable.next(), Some(2));
-        assert_eq!(peekable.next(), Some(3));
-        assert_eq!(peekable.peek_nth::<0>().cloned(), None);
+        assert_eq!(peekable.next(), None);
+
+        let peekable = Prepeek::<_, 3>::new([5, 1].into_iter()).take_while(|x| *x < 3);
+        assert_eq!(peekable.peek(), None);
+    }
+
+    #[test]
+    fn test_peek_nth_expect() {
+        let peekable = Prepeek::<_, 3>::new([1, 2].into_iter());
+        assert_eq!(peekable.peek_nth_expect(0, "front"), &1);
+        assert_eq!(peekable.peek_nth_expect(1, "second"), &2);
+    }
+
+    #[test]
+    #[should_panic(expected = "missing third: no element at offset 2 (buffered_len = 2, L = 3)")]
+    fn test_peek_nth_expect_missing() {
+        let peekable = Prepeek::<_, 3>::new([1, 2].into_iter());
+        peekable.peek_nth_expect(2, "missing third");
+    }
+
+    #[test]
+    fn test_new_skipping() {
+        let peekable = Prepeek::<_, 2>::new_skipping([1, 2, 3, 4, 5].into_iter(), 2);
+        assert_eq!(peekable.peek().cloned(), Some(3));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        let peekable = Prepeek::<_, 2>::new_skipping([1, 2].into_iter(), 0);
+        assert_eq!(peekable.peek().cloned(), Some(1));
+
+        let peekable = Prepeek::<_, 2>::new_skipping([1, 2].into_iter(), 5);
+        assert_eq!(peekable.peek().cloned(), None);
+    }
+
+    #[test]
+    fn test_peek_str() {
+        let mut peekable = Prepeek::<_, 3>::new("let x".chars());
+        assert_eq!(peekable.peek_str(), "let");
+        peekable.next();
+        peekable.next();
+        peekable.next();
+        assert_eq!(peekable.peek_str(), " x");
+        assert_eq!(peekable.next(), Some(' '));
+    }
+
+    #[test]
+    fn test_peek_min_max_by_key() {
+        let peekable = Prepeek::<_, 3>::new([3, 1, 2].into_iter());
+        assert_eq!(peekable.peek_min_by_key(|x| *x), Some((1, &1)));
+        assert_eq!(peekable.peek_max_by_key(|x| *x), Some((0, &3)));
+
+        // first on ties
+        let peekable = Prepeek::<_, 4>::new([2, 1, 2, 1].into_iter());
+        assert_eq!(peekable.peek_min_by_key(|x| *x), Some((1, &1)));
+        assert_eq!(peekable.peek_max_by_key(|x| *x), Some((0, &2)));
+
+        let peekable = Prepeek::<_, 4>::new(std::iter::empty::<i32>());
+        assert_eq!(peekable.peek_min_by_key(|x| *x), None);
+    }
+
+    #[test]
+    fn test_with_last_flag() {
+        let peekable = Prepeek::<_, 2>::new([1, 2, 3].into_iter());
+        assert_eq!(
+            peekable.with_last_flag().collect::<Vec<_>>(),
+            vec![(false, 1), (false, 2), (true, 3)]
+        );
+        let peekable = Prepeek::<_, 2>::new(std::iter::empty::<i32>());
+        assert_eq!(peekable.with_last_flag().next(), None);
+    }
+
+    #[test]
+    fn test_buffer_checksum() {
+        let mut peekable = Prepeek::<_, 4>::new(b"xabc".iter().copied());
+        peekable.next();
+        assert_eq!(peekable.buffer_checksum(), 0x352441C2);
+        assert_eq!(peekable.peek().cloned(), Some(b'a'));
+
+        let peekable = Prepeek::<_, 4>::new(std::iter::empty());
+        assert_eq!(peekable.buffer_checksum(), 0);
+    }
+
+    #[test]
+    fn test_from_raw_parts() {
+        let ring = [Some(3), None, Some(1), Some(2)];
+        let mut peekable = Prepeek::from_raw_parts(4..6, ring, 2);
+        assert_eq!(peekable.buffered_len(), 3);
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(3));
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek_nth::<3>().cloned(), Some(5));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+
+        let peekable = Prepeek::<_, 0>::from_raw_parts(1..3, [], 0);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "buffered elements must come first in the ring")]
+    fn test_from_raw_parts_gap() {
+        let ring = [Some(1), None, Some(2)];
+        let _ = Prepeek::from_raw_parts(3..5, ring, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ring_index 3 out of range for L = 3")]
+    fn test_from_raw_parts_ring_index() {
+        let ring = [Some(1), Some(2), None];
+        let _ = Prepeek::from_raw_parts(3..5, ring, 3);
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let mut peekable = Prepeek::<_, 3>::new(1..=6);
+        peekable.next();
+        peekable.buffer_retain(|x| *x != 3);
+        let (iter, ring, ring_index) = peekable.into_parts();
+        assert_eq!(ring.iter().flatten().count(), 2);
+
+        let peekable = Prepeek::from_raw_parts(iter, ring, ring_index);
+        assert_eq!(peekable.peek_refs::<2>(), Some([&2, &4]));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![2, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_consume_balanced() {
+        let mut peekable = Prepeek::<_, 2>::new(["(", "a", "(", "b", ")", ")"].into_iter());
+        assert_eq!(
+            peekable.consume_balanced(|x| *x == "(", |x| *x == ")"),
+            Some(vec!["(", "a", "(", "b", ")", ")"])
+        );
+        assert_eq!(peekable.next(), None);
+
+        let mut peekable = Prepeek::<_, 2>::new(["a", "(", ")"].into_iter());
+        assert_eq!(
+            peekable.consume_balanced(|x| *x == "(", |x| *x == ")"),
+            None
+        );
+        assert_eq!(peekable.peek().cloned(), Some("a"));
+
+        let mut peekable = Prepeek::<_, 2>::new(["(", "(", ")"].into_iter());
+        assert_eq!(
+            peekable.consume_balanced(|x| *x == "(", |x| *x == ")"),
+            None
+        );
+        assert_eq!(peekable.next(), None);
+    }
+
+    #[test]
+    fn test_peek_limit() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter());
+        peekable.set_peek_limit(1);
+        assert_eq!(peekable.peek().cloned(), Some(1));
         assert_eq!(peekable.peek_nth::<1>().cloned(), None);
-        assert_eq!(peekable.peek_nth::<2>().cloned(), None);
+        assert_eq!(peekable.peek_nth_fetch(1), None);
+        assert_eq!(peekable.peek_refs::<2>(), None);
+        assert_eq!(peekable.peek_ends(), (Some(&1), Some(&1)));
+        assert_eq!(peekable.peek_sum::<i32>(), 1);
+
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek().cloned(), Some(2));
+        peekable.set_peek_limit(usize::MAX);
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(4));
+    }
+
+    #[test]
+    fn test_scan() {
+        let mut peekable = Prepeek::<_, 2>::new([1, 2, 3].into_iter()).scan(0, |sum, x| {
+            *sum += x;
+            Some(*sum)
+        });
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(3));
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(6));
+    }
+
+    #[test]
+    fn test_buffer_to_vecdeque() {
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 3, 4].into_iter());
+        peekable.next();
+        let deque = peekable.buffer_to_vecdeque();
+        assert_eq!(deque.front(), peekable.peek());
+        assert_eq!(deque, VecDeque::from([2, 3, 4]));
+        assert_eq!(peekable.buffered_len(), 3);
+    }
+
+    #[test]
+    fn test_peek_flatten() {
+        let mut peekable = Prepeek::<_, 2>::new([Some(1), None, Some(3)].into_iter());
+        assert_eq!(peekable.peek_flatten().cloned(), Some(1));
+        peekable.next();
+        assert_eq!(peekable.peek_flatten().cloned(), None);
+        assert_eq!(peekable.peek().cloned(), Some(None));
+        peekable.next();
+        assert_eq!(peekable.peek_flatten().cloned(), Some(3));
+        peekable.next();
+        assert_eq!(peekable.peek_flatten().cloned(), None);
+        assert_eq!(peekable.peek().cloned(), None);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let mut peekable = Prepeek::<_, 2>::new([1, 1, 2, 3, 3].into_iter()).dedup();
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(3));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_peek_sample() {
+        let peekable = Prepeek::<_, 5>::new([1, 2, 3, 4, 5].into_iter());
+        assert_eq!(peekable.peek_sample(3), vec![&1, &3, &5]);
+        assert_eq!(peekable.peek_sample(2), vec![&1, &5]);
+        assert_eq!(peekable.peek_sample(1), vec![&1]);
+        assert_eq!(peekable.peek_sample(0), Vec::<&i32>::new());
+        assert_eq!(peekable.peek_sample(4), vec![&1, &2, &3, &5]);
+        assert_eq!(peekable.peek_sample(10), vec![&1, &2, &3, &4, &5]);
+    }
+
+    #[test]
+    fn test_peek_find_map() {
+        let peekable = Prepeek::<_, 3>::new(['a', 'b', 'c'].into_iter());
+        assert_eq!(
+            peekable.peek_find_map(|c| (*c == 'b').then(|| c.to_ascii_uppercase())),
+            Some((1, 'B'))
+        );
+        assert_eq!(peekable.peek_find_map(|c| (*c == 'd').then_some(())), None);
+    }
+
+    #[test]
+    fn test_auto_refill() {
+        let source = || {
+            let mut xs = [Some(1), None, None, Some(2)].into_iter();
+            std::iter::from_fn(move || xs.next().flatten())
+        };
+
+        let mut peekable = Prepeek::<_, 2>::new(source());
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek().cloned(), None);
+        assert_eq!(peekable.next(), Some(2));
+
+        let mut peekable = Prepeek::<_, 2>::new(source());
+        peekable.set_auto_refill(false);
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.next(), None);
+        assert_eq!(peekable.peek_nth_fetch(0), None);
+
+        let mut peekable = Prepeek::<_, 0>::new(source());
+        peekable.set_auto_refill(false);
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.next(), None);
+        assert_eq!(peekable.next(), None);
+    }
+
+    #[test]
+    fn test_peek_pair_key() {
+        use std::collections::HashMap;
+
+        let table = HashMap::from([((1, Some(2)), "pair"), ((2, None), "single")]);
+        let key = |a: Option<&i32>, b: Option<&i32>| (*a.unwrap(), b.cloned());
+
+        let mut peekable = Prepeek::<_, 2>::new([1, 2].into_iter());
+        assert_eq!(table.get(&peekable.peek_pair_key(key)), Some(&"pair"));
+        peekable.next();
+        assert_eq!(table.get(&peekable.peek_pair_key(key)), Some(&"single"));
+    }
+
+    #[test]
+    fn test_peek_chars_match() {
+        let peekable = Prepeek::<_, 3>::new(['f', 'n', ' '].into_iter());
+        assert!(peekable.peek_chars_match("fn"));
+        assert!(peekable.peek_chars_match("fn "));
+        assert!(peekable.peek_chars_match(""));
+        assert!(!peekable.peek_chars_match("fo"));
+        assert!(!peekable.peek_chars_match("fn x"));
+    }
+
+    #[test]
+    fn test_delta_encode() {
+        let mut peekable = Prepeek::<_, 2>::new([10, 13, 12].into_iter()).delta_encode();
+        assert_eq!(peekable.peek().cloned(), Some(10));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(3));
+        assert_eq!(peekable.next(), Some(10));
+        assert_eq!(peekable.next(), Some(3));
+        assert_eq!(peekable.next(), Some(-1));
+        assert_eq!(peekable.next(), None);
+    }
+
+    #[test]
+    fn test_peek_runs() {
+        let peekable = Prepeek::<_, 4>::new([1, 2, 3, 2].into_iter());
+        assert_eq!(peekable.peek_ascending_run(), 3);
+        assert_eq!(peekable.peek_descending_run(), 1);
+
+        let peekable = Prepeek::<_, 4>::new([4, 3, 2, 1, 0].into_iter());
+        assert_eq!(peekable.peek_ascending_run(), 1);
+        assert_eq!(peekable.peek_descending_run(), 4);
+
+        let peekable = Prepeek::<_, 4>::new(std::iter::empty::<i32>());
+        assert_eq!(peekable.peek_ascending_run(), 0);
+    }
+
+    #[test]
+    fn test_consume_exactly() {
+        let mut peekable = Prepeek::<_, 2>::new([1, 2].into_iter());
+        assert_eq!(peekable.consume_exactly(3), Err(vec![1, 2]));
+        assert_eq!(peekable.next(), None);
+
+        let mut peekable = Prepeek::<_, 2>::new([1, 2, 3, 4].into_iter());
+        assert_eq!(peekable.consume_exactly(3), Ok(vec![1, 2, 3]));
+        assert_eq!(peekable.consume_exactly(0), Ok(vec![]));
+        assert_eq!(peekable.peek().cloned(), Some(4));
+    }
+
+    #[test]
+    fn test_peek_key() {
+        use std::collections::HashMap;
+
+        let rules = HashMap::from([(vec![1, 0], "odd even"), (vec![0], "even")]);
+        let mut peekable = Prepeek::<_, 3>::new([1, 2, 4].into_iter());
+        let key = peekable.peek_key(2, |x| x % 2);
+        assert_eq!(key, vec![1, 0]);
+        assert_eq!(rules.get(&key), Some(&"odd even"));
+
+        peekable.next();
+        peekable.next();
+        assert_eq!(rules.get(&peekable.peek_key(2, |x| x % 2)), Some(&"even"));
+    }
+
+    #[test]
+    fn test_positions_in_buffer() {
+        let peekable = Prepeek::<_, 5>::new([1, 2, 1, 3, 1].into_iter());
+        assert_eq!(peekable.positions_in_buffer(|x| *x == 1), vec![0, 2, 4]);
+        assert_eq!(
+            peekable.positions_in_buffer(|x| *x == 4),
+            Vec::<usize>::new()
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut peekable = Prepeek::<_, 2>::new([1, 3, 5].into_iter()).merge([2, 4, 6].into_iter());
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![2, 3, 4, 5, 6]);
+
+        let peekable = Prepeek::<_, 2>::new([3].into_iter()).merge([1, 2, 4].into_iter());
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn test_max_peek_offset() {
+        let mut peekable = Prepeek::<_, 4>::new([1, 2, 3, 4].into_iter());
+        assert_eq!(peekable.max_peek_offset(), 0);
+        peekable.peek_nth::<2>();
+        peekable.peek_nth::<0>();
+        assert_eq!(peekable.max_peek_offset(), 2);
+        peekable.next();
+        peekable.peek();
+        assert_eq!(peekable.max_peek_offset(), 2);
+        // misses are recorded, capped at L
+        peekable.peek_nth::<3>();
+        assert_eq!(peekable.max_peek_offset(), 3);
+        peekable.peek_nth::<100>();
+        assert_eq!(peekable.max_peek_offset(), 4);
+
+        // whole-buffer reads are not recorded
+        let mut peekable = Prepeek::<_, 8>::new([1, 2].into_iter());
+        assert_eq!(peekable.peek_sum::<i32>(), 3);
+        assert_eq!(peekable.buffer_to_vecdeque().len(), 2);
+        assert_eq!(peekable.max_peek_offset(), 0);
+        peekable.peek_nth_fetch(5);
+        assert_eq!(peekable.max_peek_offset(), 5);
+
+        let mut peekable = Prepeek::<_, 4>::new(1..=4);
+        peekable.peek_nth_mut_fetch(3);
+        assert_eq!(peekable.max_peek_offset(), 3);
+    }
+
+    #[test]
+    fn test_find_pair() {
+        let peekable = Prepeek::<_, 4>::new([1, 2, 10, 3].into_iter());
+        assert_eq!(peekable.find_pair(|a, b| *b > *a * 2), Some(1));
+        assert_eq!(peekable.find_pair(|a, b| *b < *a), Some(2));
+        assert_eq!(peekable.find_pair(|a, b| a == b), None);
+
+        let peekable = Prepeek::<_, 4>::new([1].into_iter());
+        assert_eq!(peekable.find_pair(|_, _| true), None);
+    }
+
+    #[test]
+    fn test_cloned_copied() {
+        let xs = [1, 2, 3];
+        let mut peekable = Prepeek::<_, 2>::new(xs.iter());
+        peekable.next();
+        let peekable = peekable.cloned();
+        let front: Option<&i32> = peekable.peek();
+        assert_eq!(front, Some(&2));
+        assert_eq!(peekable.collect::<Vec<i32>>(), vec![2, 3]);
+
+        let peekable = Prepeek::<_, 2>::new(xs.iter()).copied();
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        assert_eq!(peekable.collect::<Vec<i32>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let mut peekable = Prepeek::<_, 3>::new(1..=5);
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.next(), Some(2));
+        assert_eq!(peekable.buffered_len(), 3);
+        peekable.restore(cp);
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.buffered_len(), 3);
+
+        // backtracking goes further than the lookahead
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(4));
+        assert_eq!(peekable.by_ref().take(3).collect::<Vec<_>>(), vec![2, 3, 4]);
+        peekable.restore(cp);
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(3));
+        assert_eq!(peekable.next(), Some(1));
+
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.next(), Some(2));
+        peekable.discard(cp);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_put_back_during_checkpoint() {
+        let mut peekable = Prepeek::<_, 4>::new(1..=5);
+        peekable.set_overflow_policy(OverflowPolicy::ReturnEvicted);
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.put_back(0), Some(5));
+        peekable.restore(cp);
+        assert_eq!(peekable.buffered_len(), 4);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 0, 2, 3, 4]);
+
+        let mut peekable = Prepeek::<_, 3>::new(1..=10);
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.put_back(0), None);
+        peekable.restore(cp);
+        assert_eq!(
+            peekable.collect::<Vec<_>>(),
+            vec![1, 0, 2, 3, 5, 6, 7, 8, 9, 10]
+        );
+    }
+
+    #[test]
+    fn test_checkpoint_logs_all_consumption() {
+        let mut peekable = Prepeek::<_, 3>::new(1..=5);
+        let cp = peekable.checkpoint();
+        let mut txn = peekable.begin();
+        txn.next();
+        txn.commit();
+        assert_eq!(peekable.take_prefetched().next(), Some(2));
+        peekable.restore(cp);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_peek_limit_internal_lookahead() {
+        let mut peekable = Prepeek::<_, 2>::new(1..=3);
+        peekable.set_peek_limit(0);
+        assert_eq!(
+            peekable.with_last_flag().collect::<Vec<_>>(),
+            vec![(false, 1), (false, 2), (true, 3)]
+        );
+
+        let mut peekable = Prepeek::<_, 2>::new(1..=3);
+        peekable.set_peek_limit(0);
+        assert_eq!(
+            peekable.with_lookahead_pairs().collect::<Vec<_>>(),
+            vec![(1, Some(2)), (2, Some(3)), (3, None)]
+        );
+
+        let mut peekable = Prepeek::<_, 2>::new("(a)b".chars());
+        peekable.set_peek_limit(0);
+        let group = peekable.consume_balanced(|c| *c == '(', |c| *c == ')');
+        assert_eq!(group, Some("(a)".chars().collect()));
+    }
+
+    #[test]
+    fn test_checkpoint_keeps_iterating() {
+        let mut peekable = Prepeek::<_, 2>::new("(abc)d".chars());
+        let cp = peekable.checkpoint();
+        let group = peekable.consume_balanced(|c| *c == '(', |c| *c == ')');
+        assert_eq!(group, Some("(abc)".chars().collect()));
+        peekable.restore(cp);
+        assert_eq!(peekable.collect::<String>(), "(abc)d");
+
+        let mut peekable = Prepeek::<_, 2>::new(1..=5);
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.consume_exactly(3), Ok(vec![1, 2, 3]));
+        assert_eq!(peekable.by_ref().collect::<Vec<_>>(), vec![4, 5]);
+        peekable.restore(cp);
+        assert_eq!(peekable.peek_nth::<1>().cloned(), Some(2));
+        let (buffered, rest) = peekable.split();
+        assert_eq!(buffered.collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(rest.collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        let mut peekable = Prepeek::<_, 0>::new(1..=3);
+        let cp = peekable.checkpoint();
+        assert_eq!(peekable.by_ref().collect::<Vec<_>>(), vec![1, 2, 3]);
+        peekable.restore(cp);
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_into_parts_with_spill() {
+        let mut peekable = Prepeek::<_, 1>::new(1..=3);
+        let cp = peekable.checkpoint();
+        peekable.next();
+        peekable.restore(cp);
+        let _ = peekable.into_parts();
     }
 }
@@ -0,0 +1,122 @@
+use crate::Prepeek;
+
+/// A speculative consumption of a [`Prepeek`], which is undone unless committed.
+///
+/// This `struct` is created by [`Prepeek::begin`]. Elements consumed through the transaction are
+/// kept aside, and pushed back to the front of the [`Prepeek`] on [`Txn::rollback`] or when the
+/// transaction is dropped without [`Txn::commit`].
+///
+/// The buffer is not refilled while the transaction is open, so at most the elements buffered
+/// when it began can be consumed, and rolling back never evicts anything.
+pub struct Txn<'a, I: Iterator, const L: usize> {
+    inner: &'a mut Prepeek<I, L>,
+    consumed: Vec<I::Item>,
+}
+
+impl<'a, I: Iterator, const L: usize> Txn<'a, I, L> {
+    pub(crate) fn new(inner: &'a mut Prepeek<I, L>) -> Self {
+        Self {
+            inner,
+            consumed: Vec::new(),
+        }
+    }
+
+    /// Returns a reference to the next value of the transaction without consuming it.
+    pub fn peek(&self) -> Option<&I::Item> {
+        self.inner.peek()
+    }
+
+    /// Returns a reference to the `nth` value of the transaction without consuming it.
+    pub fn peek_nth<const N: usize>(&self) -> Option<&I::Item> {
+        self.inner.peek_nth::<N>()
+    }
+
+    /// Consumes the next value, returning a reference to it.
+    ///
+    /// The value itself is kept by the transaction, and is handed out by [`Txn::commit`].
+    // not an `Iterator`, as the value has to stay with the transaction
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&I::Item> {
+        let v = self.inner.pop_front()?;
+        self.consumed.push(v);
+        self.consumed.last()
+    }
+
+    /// Returns the values consumed so far, in order.
+    pub fn consumed(&self) -> &[I::Item] {
+        &self.consumed
+    }
+
+    /// Makes the consumption permanent, returning the consumed values.
+    pub fn commit(mut self) -> Vec<I::Item> {
+        for v in &self.consumed {
+            self.inner.log_consumed(v);
+        }
+        std::mem::take(&mut self.consumed)
+    }
+
+    /// Pushes the consumed values back to the front of the [`Prepeek`].
+    ///
+    /// This is the same as dropping the transaction.
+    pub fn rollback(self) {}
+}
+
+impl<I: Iterator, const L: usize> Drop for Txn<'_, I, L> {
+    fn drop(&mut self) {
+        while let Some(v) = self.consumed.pop() {
+            self.inner.push_front(v);
+        }
+        self.inner.fill();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollback() {
+        let mut peekable = Prepeek::<_, 3>::new(1..=5);
+        let mut txn = peekable.begin();
+        assert_eq!(txn.next().cloned(), Some(1));
+        assert_eq!(txn.next().cloned(), Some(2));
+        assert_eq!(txn.peek().cloned(), Some(3));
+        txn.rollback();
+        assert_eq!(peekable.peek().cloned(), Some(1));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_drop_rolls_back() {
+        let mut peekable = Prepeek::<_, 2>::new(1..=5);
+        {
+            let mut txn = peekable.begin();
+            txn.next();
+        }
+        assert_eq!(peekable.peek().cloned(), Some(1));
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut peekable = Prepeek::<_, 3>::new(1..=5);
+        let mut txn = peekable.begin();
+        txn.next();
+        txn.next();
+        assert_eq!(txn.consumed(), &[1, 2]);
+        assert_eq!(txn.commit(), vec![1, 2]);
+        assert_eq!(peekable.peek().cloned(), Some(3));
+        assert_eq!(peekable.peek_nth::<2>().cloned(), Some(5));
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_bounded_by_buffer() {
+        let mut peekable = Prepeek::<_, 2>::new(1..=5);
+        let mut txn = peekable.begin();
+        txn.next();
+        txn.next();
+        assert_eq!(txn.next(), None);
+        txn.rollback();
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,97 @@
+use crate::Prepeek;
+
+/// Wrapper to a [`Prepeek`] calling a closure whenever next() leaves the buffer under-filled.
+///
+/// This `struct` is created by [`Prepeek::on_underfill`].
+pub struct OnUnderfill<I: Iterator, F, const L: usize> {
+    inner: Prepeek<I, L>,
+    threshold: f64,
+    cb: F,
+}
+
+impl<I: Iterator, F: FnMut(), const L: usize> OnUnderfill<I, F, L> {
+    pub(crate) fn new(inner: Prepeek<I, L>, threshold: f64, cb: F) -> Self {
+        Self {
+            inner,
+            threshold,
+            cb,
+        }
+    }
+
+    /// Like [`Prepeek::peek`].
+    pub fn peek(&self) -> Option<&I::Item> {
+        self.inner.peek()
+    }
+
+    /// Like [`Prepeek::peek_nth`].
+    pub fn peek_nth<const N: usize>(&self) -> Option<&I::Item> {
+        self.inner.peek_nth::<N>()
+    }
+
+    /// Like [`Prepeek::fill_ratio`].
+    pub fn fill_ratio(&self) -> f64 {
+        self.inner.fill_ratio()
+    }
+
+    /// Returns a reference to the wrapped [`Prepeek`].
+    pub fn get_ref(&self) -> &Prepeek<I, L> {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the wrapped [`Prepeek`].
+    ///
+    /// Elements consumed directly through it do not call the closure.
+    pub fn get_mut(&mut self) -> &mut Prepeek<I, L> {
+        &mut self.inner
+    }
+
+    /// Unwraps the [`Prepeek`], dropping the closure.
+    pub fn into_inner(self) -> Prepeek<I, L> {
+        self.inner
+    }
+}
+
+impl<I: Iterator, F: FnMut(), const L: usize> Iterator for OnUnderfill<I, F, L> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let v = self.inner.next();
+        if v.is_some() && self.inner.fill_ratio() < self.threshold {
+            (self.cb)();
+        }
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_on_underfill() {
+        let mut count = 0;
+        let mut peekable =
+            Prepeek::<_, 4>::new([1, 2].into_iter()).on_underfill(0.5, || count += 1);
+        assert_eq!(peekable.fill_ratio(), 0.5);
+        assert_eq!(peekable.next(), Some(1));
+        assert_eq!(peekable.peek().cloned(), Some(2));
+        assert_eq!(peekable.next(), Some(2));
+        assert_eq!(peekable.next(), None);
+        assert_eq!(peekable.fill_ratio(), 0.0);
+        drop(peekable);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_on_underfill_inner() {
+        let mut count = 0;
+        let mut peekable = Prepeek::<_, 2>::new(1..=4).on_underfill(1.0, || count += 1);
+        assert_eq!(peekable.get_ref().buffered_len(), 2);
+        assert_eq!(peekable.get_mut().peek_nth_fetch(1).cloned(), Some(2));
+        assert_eq!(peekable.get_mut().put_back(0), None);
+        assert_eq!(peekable.next(), Some(0));
+        let peekable = peekable.into_inner();
+        assert_eq!(peekable.collect::<Vec<_>>(), vec![1, 3, 4]);
+        assert_eq!(count, 0);
+    }
+}